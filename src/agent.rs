@@ -0,0 +1,513 @@
+use crate::Move;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+// Cell codes used by Observation::grid
+pub const EMPTY: isize = 0;
+pub const BODY: isize = 1;
+pub const HEAD: isize = 2;
+pub const FRUIT: isize = 3;
+pub const UNKNOWN: isize = -1;
+
+// Snapshot of the environment handed to agents, independent of Raylib
+#[derive(Clone)]
+pub struct Observation {
+    pub snake: Vec<(isize, isize)>,
+    pub fruit: Option<(isize, isize)>,
+    pub velocity: (isize, isize),
+    pub board_size: (isize, isize),
+    pub wrap: bool,
+    // Sight radius around the head, cells further away show up as UNKNOWN in the grid
+    pub fog: Option<isize>,
+    // Row-major board shape, cells marked false count as off the board
+    pub mask: Option<Vec<bool>>,
+    // Fruit is only eaten on Move::EA, otherwise the snake passes over it
+    pub eat_requires_action: bool,
+}
+
+// Anything able to steer the snake, used by both windowed and headless runs
+pub trait Agent {
+    fn decide(&mut self, obs: &Observation) -> Move;
+    // Cells the agent currently intends to visit, starting at the head, for drawing
+    fn planned_path(&self) -> Option<&[(isize, isize)]> {
+        None
+    }
+}
+
+// Plain closures work as agents, handy for scripted runs
+impl<F: FnMut(&Observation) -> Move> Agent for F {
+    fn decide(&mut self, obs: &Observation) -> Move {
+        self(obs)
+    }
+}
+
+// Heads straight for the fruit, turning away only when the preferred cell is deadly
+#[derive(Default)]
+pub struct GreedyAgent {
+    path: Vec<(isize, isize)>,
+}
+
+impl GreedyAgent {
+    // Route the agent follows when nothing is in the way: first line up with the
+    // fruit across the current heading, then run along the heading to reach it
+    fn plan(obs: &Observation, fruit: (isize, isize)) -> Vec<(isize, isize)> {
+        let mut cell = obs.snake[0];
+        let mut path = vec![cell];
+        let across_first = obs.velocity.0 != 0;
+        for across in [across_first, !across_first] {
+            if across {
+                while cell.1 != fruit.1 {
+                    cell.1 += (fruit.1 - cell.1).signum();
+                    path.push(cell);
+                }
+            } else {
+                while cell.0 != fruit.0 {
+                    cell.0 += (fruit.0 - cell.0).signum();
+                    path.push(cell);
+                }
+            }
+        }
+        path
+    }
+}
+
+impl Observation {
+    // Cell the head would enter after taking the given move
+    pub fn next_head(&self, mv: &Move) -> (isize, isize) {
+        let (x, y) = self.velocity;
+        let (dx, dy) = match mv {
+            Move::LT => (y, -x),
+            Move::RT => (-y, x),
+            Move::FW | Move::BW | Move::PS | Move::EA => (x, y),
+        };
+        let next = (self.snake[0].0 + dx, self.snake[0].1 + dy);
+        if self.wrap {
+            (
+                next.0.rem_euclid(self.board_size.0),
+                next.1.rem_euclid(self.board_size.1),
+            )
+        } else {
+            next
+        }
+    }
+    // When the rules want it, going straight on becomes eating if that lands on the fruit
+    // or leaves the one the head passed over without eating
+    pub fn with_eating(&self, mv: Move) -> Move {
+        let head = self.snake[0];
+        let at_fruit = |fruit| fruit == head || fruit == self.next_head(&Move::FW);
+        match mv {
+            Move::FW if self.eat_requires_action && self.fruit.is_some_and(at_fruit) => Move::EA,
+            mv => mv,
+        }
+    }
+    pub fn is_on_board(&self, cell: (isize, isize)) -> bool {
+        let in_bounds =
+            cell.0 >= 0 && cell.1 >= 0 && cell.0 < self.board_size.0 && cell.1 < self.board_size.1;
+        in_bounds
+            && self
+                .mask
+                .as_ref()
+                .is_none_or(|mask| mask[(cell.1 * self.board_size.0 + cell.0) as usize])
+    }
+    pub fn is_safe(&self, cell: (isize, isize)) -> bool {
+        self.is_on_board(cell) && !self.snake[..self.snake.len() - 1].contains(&cell)
+    }
+    // Free run from the head ahead, behind, left and right of the heading until a wall or
+    // the body, as a share of the board along that axis
+    // With wrap on, a line with nothing in it wraps around and counts as the full board
+    pub fn wall_distances(&self) -> [f64; 4] {
+        let (x, y) = self.velocity;
+        [(x, y), (-x, -y), (y, -x), (-y, x)].map(|(dx, dy)| {
+            let span = if dx != 0 {
+                self.board_size.0
+            } else {
+                self.board_size.1
+            };
+            let mut cell = self.snake[0];
+            let mut free = 0;
+            while free < span {
+                cell = (cell.0 + dx, cell.1 + dy);
+                if self.wrap {
+                    cell = (
+                        cell.0.rem_euclid(self.board_size.0),
+                        cell.1.rem_euclid(self.board_size.1),
+                    );
+                }
+                if !self.is_safe(cell) {
+                    break;
+                }
+                free += 1;
+            }
+            free as f64 / span as f64
+        })
+    }
+    // Fingerprint of everything an agent can see, equal for identical observations
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.snake.hash(&mut hasher);
+        self.fruit.hash(&mut hasher);
+        self.velocity.hash(&mut hasher);
+        self.board_size.hash(&mut hasher);
+        self.wrap.hash(&mut hasher);
+        self.fog.hash(&mut hasher);
+        self.mask.hash(&mut hasher);
+        self.eat_requires_action.hash(&mut hasher);
+        hasher.finish()
+    }
+    // Board as rows of cell codes, indexed as grid[y][x] with the size taken from board_size
+    pub fn grid(&self) -> Vec<Vec<isize>> {
+        let mut grid = vec![vec![EMPTY; self.board_size.0 as usize]; self.board_size.1 as usize];
+        if let Some(fruit) = self.fruit.filter(|&cell| self.is_on_board(cell)) {
+            grid[fruit.1 as usize][fruit.0 as usize] = FRUIT;
+        }
+        for (idx, &cell) in self.snake.iter().enumerate().rev() {
+            if self.is_on_board(cell) {
+                grid[cell.1 as usize][cell.0 as usize] = if idx == 0 { HEAD } else { BODY };
+            }
+        }
+        if let Some(radius) = self.fog {
+            let head = self.snake[0];
+            for (y, row) in grid.iter_mut().enumerate() {
+                for (x, cell) in row.iter_mut().enumerate() {
+                    if (x as isize - head.0).abs().max((y as isize - head.1).abs()) > radius {
+                        *cell = UNKNOWN;
+                    }
+                }
+            }
+        }
+        grid
+    }
+    // Number of free cells reachable from `start`, treating the body as walls
+    // The tail is left open since it moves away on the next step, and fogged cells are
+    // hoped to be free
+    pub fn reachable_area(&self, start: (isize, isize)) -> usize {
+        self.reachable_cells(start).len()
+    }
+    // Like reachable_area, but `start` may be part of the snake, as the head always is,
+    // in which case only the free cells around it count
+    pub fn reachable_area_around(&self, start: (isize, isize)) -> usize {
+        if !self.is_on_board(start) {
+            return 0;
+        }
+        self.flood(start).len() - !self.is_safe(start) as usize
+    }
+    // Free cells the head can still get to through any of its possible moves
+    pub fn reachable_from_head(&self) -> Vec<(isize, isize)> {
+        let mut cells = Vec::new();
+        for mv in [Move::FW, Move::LT, Move::RT] {
+            let next = self.next_head(&mv);
+            if !cells.contains(&next) {
+                cells.extend(self.reachable_cells(next));
+            }
+        }
+        cells
+    }
+    fn reachable_cells(&self, start: (isize, isize)) -> Vec<(isize, isize)> {
+        if !self.is_safe(start) {
+            return Vec::new();
+        }
+        self.flood(start)
+    }
+    // Every cell connected to `start` through free ones, `start` itself included whatever
+    // is on it
+    fn flood(&self, start: (isize, isize)) -> Vec<(isize, isize)> {
+        let mut visited: Vec<Vec<bool>> = self
+            .grid()
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&cell| cell == BODY || cell == HEAD)
+                    .collect()
+            })
+            .collect();
+        let tail = self.snake[self.snake.len() - 1];
+        if self.snake.len() > 1 && self.is_on_board(tail) {
+            visited[tail.1 as usize][tail.0 as usize] = false;
+        }
+
+        let mut stack = vec![start];
+        visited[start.1 as usize][start.0 as usize] = true;
+        let mut area = Vec::new();
+        while let Some((x, y)) = stack.pop() {
+            area.push((x, y));
+            for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let mut next = (x + dx, y + dy);
+                if self.wrap {
+                    next = (
+                        next.0.rem_euclid(self.board_size.0),
+                        next.1.rem_euclid(self.board_size.1),
+                    );
+                }
+                if !self.is_on_board(next) {
+                    continue;
+                }
+                let seen = &mut visited[next.1 as usize][next.0 as usize];
+                if !*seen {
+                    *seen = true;
+                    stack.push(next);
+                }
+            }
+        }
+        area
+    }
+}
+
+impl Agent for GreedyAgent {
+    fn decide(&mut self, obs: &Observation) -> Move {
+        self.path = match obs.fruit {
+            Some(fruit) => GreedyAgent::plan(obs, fruit),
+            None => Vec::new(),
+        };
+
+        let preferred = match obs.fruit {
+            Some(fruit) => {
+                let head = obs.snake[0];
+                let delta = (fruit.0 - head.0, fruit.1 - head.1);
+                let (vx, vy) = obs.velocity;
+
+                // Projections of the fruit offset onto the heading and its right-hand normal
+                let ahead = delta.0 * vx + delta.1 * vy;
+                let right = delta.0 * -vy + delta.1 * vx;
+
+                if right > 0 {
+                    Move::RT
+                } else if right < 0 || ahead < 0 {
+                    Move::LT
+                } else {
+                    Move::FW
+                }
+            }
+            None => Move::FW,
+        };
+
+        let mv = [preferred, Move::FW, Move::LT, Move::RT]
+            .into_iter()
+            .find(|mv| obs.is_safe(obs.next_head(mv)))
+            .unwrap_or(Move::FW);
+        obs.with_eating(mv)
+    }
+    fn planned_path(&self) -> Option<&[(isize, isize)]> {
+        Some(&self.path)
+    }
+}
+
+// Picks the move leaving the largest open region, preferring the fruit among equals
+pub struct FloodFillAgent;
+
+impl Agent for FloodFillAgent {
+    fn decide(&mut self, obs: &Observation) -> Move {
+        let distance_to_fruit = |cell: (isize, isize)| match obs.fruit {
+            Some(fruit) => (fruit.0 - cell.0).abs() + (fruit.1 - cell.1).abs(),
+            None => 0,
+        };
+
+        let mv = [Move::FW, Move::LT, Move::RT]
+            .into_iter()
+            .max_by_key(|mv| {
+                let next = obs.next_head(mv);
+                (obs.reachable_area(next), -distance_to_fruit(next))
+            })
+            .unwrap_or(Move::FW);
+        obs.with_eating(mv)
+    }
+}
+
+// Remembers the move a deterministic agent made for each state it saw, so replaying
+// the same states skips the search
+// Once `capacity` states are stored the cache starts over rather than tracking age
+// Cells an agent plans to visit, as given by planned_path
+type Path = Vec<(isize, isize)>;
+
+// The plan that came with each decision is kept too, so a hit doesn't show a stale route
+pub struct CachedAgent {
+    inner: Box<dyn Agent>,
+    cache: HashMap<u64, (Move, Option<Path>)>,
+    capacity: usize,
+    path: Option<Path>,
+}
+
+impl CachedAgent {
+    pub fn new(inner: Box<dyn Agent>, capacity: usize) -> Self {
+        Self {
+            inner,
+            cache: HashMap::new(),
+            capacity: capacity.max(1),
+            path: None,
+        }
+    }
+}
+
+impl Agent for CachedAgent {
+    fn decide(&mut self, obs: &Observation) -> Move {
+        let key = obs.state_hash();
+        if let Some((mv, path)) = self.cache.get(&key) {
+            self.path = path.clone();
+            return *mv;
+        }
+        let mv = self.inner.decide(obs);
+        self.path = self.inner.planned_path().map(<[_]>::to_vec);
+        if self.cache.len() >= self.capacity {
+            self.cache.clear();
+        }
+        self.cache.insert(key, (mv, self.path.clone()));
+        mv
+    }
+    fn planned_path(&self) -> Option<&[(isize, isize)]> {
+        self.path.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observation(snake: Vec<(isize, isize)>, velocity: (isize, isize)) -> Observation {
+        Observation {
+            snake,
+            fruit: None,
+            velocity,
+            board_size: (10, 10),
+            wrap: false,
+            fog: None,
+            mask: None,
+            eat_requires_action: false,
+        }
+    }
+
+    #[test]
+    fn greedy_path_runs_from_the_head_to_the_fruit() {
+        let mut obs = observation(vec![(5, 5), (4, 5)], (1, 0));
+        obs.fruit = Some((2, 9));
+        let mut agent = GreedyAgent::default();
+        agent.decide(&obs);
+        let path = agent.planned_path().unwrap();
+        assert_eq!(path.first(), Some(&(5, 5)));
+        assert_eq!(path.last(), Some(&(2, 9)));
+        for pair in path.windows(2) {
+            let step = (pair[1].0 - pair[0].0).abs() + (pair[1].1 - pair[0].1).abs();
+            assert_eq!(step, 1, "{:?} jumps", pair);
+        }
+    }
+
+    #[test]
+    fn flood_fill_stays_out_of_the_pocket_greedy_enters() {
+        // The body walls off the top-left 3x3 corner, where the fruit is, and the head
+        // sits at its only way in
+        let mut obs = observation(
+            vec![
+                (0, 3),
+                (1, 3),
+                (2, 3),
+                (3, 3),
+                (3, 2),
+                (3, 1),
+                (3, 0),
+                (4, 0),
+                (5, 0),
+            ],
+            (-1, 0),
+        );
+        obs.board_size = (6, 6);
+        obs.fruit = Some((0, 0));
+        let space = |mv: Move| obs.reachable_area(obs.next_head(&mv));
+
+        let greedy = GreedyAgent::default().decide(&obs);
+        let flood = FloodFillAgent.decide(&obs);
+        assert_eq!(space(greedy), 9);
+        assert_eq!(space(flood), 19);
+    }
+
+    #[test]
+    fn grid_is_indexed_by_row_then_column() {
+        let mut obs = observation(vec![(4, 1), (3, 1)], (1, 0));
+        obs.board_size = (5, 3);
+        obs.fruit = Some((0, 2));
+        let grid = obs.grid();
+        assert_eq!(grid.len(), 3);
+        assert!(grid.iter().all(|row| row.len() == 5));
+        assert_eq!(grid[1][4], HEAD);
+        assert_eq!(grid[1][3], BODY);
+        assert_eq!(grid[2][0], FRUIT);
+        let filled = grid.iter().flatten().filter(|&&cell| cell != EMPTY).count();
+        assert_eq!(filled, 3);
+    }
+
+    #[test]
+    fn wall_distances_stop_at_walls_and_the_body() {
+        let mut obs = observation(vec![(3, 5), (2, 5), (1, 5)], (1, 0));
+        let mut mask = vec![true; 100];
+        mask[5 * 10 + 7] = false;
+        obs.mask = Some(mask);
+        // Ahead, behind, left and right of a snake heading right
+        assert_eq!(obs.wall_distances(), [0.3, 0.0, 0.5, 0.4]);
+
+        let mut wrapped = observation(vec![(3, 5)], (1, 0));
+        wrapped.wrap = true;
+        assert_eq!(wrapped.wall_distances(), [1.0; 4]);
+    }
+
+    #[test]
+    fn cached_agent_skips_the_search_on_a_seen_state() {
+        let calls = std::rc::Rc::new(std::cell::Cell::new(0));
+        let counted = calls.clone();
+        let mut greedy = GreedyAgent::default();
+        let counting = move |obs: &Observation| {
+            counted.set(counted.get() + 1);
+            greedy.decide(obs)
+        };
+        let mut agent = CachedAgent::new(Box::new(counting), 2);
+        let mut obs = observation(vec![(5, 5), (4, 5)], (1, 0));
+        obs.fruit = Some((5, 1));
+        let other = observation(vec![(2, 2)], (0, 1));
+
+        let first = agent.decide(&obs).name();
+        assert_eq!(agent.decide(&obs).name(), first);
+        assert_eq!(calls.get(), 1);
+        agent.decide(&other);
+        assert_eq!(calls.get(), 2);
+        assert_eq!(agent.decide(&obs).name(), first);
+        assert_eq!(calls.get(), 2);
+        // A full cache starts over, so the next new state pushes the others out
+        agent.decide(&observation(vec![(7, 7)], (0, 1)));
+        agent.decide(&obs);
+        assert_eq!(calls.get(), 4);
+    }
+
+    #[test]
+    fn agents_ask_to_eat_only_when_the_rules_want_it() {
+        let mut ahead = observation(vec![(5, 5), (4, 5)], (1, 0));
+        ahead.fruit = Some((6, 5));
+        let mut under = observation(vec![(5, 5), (4, 5)], (1, 0));
+        under.fruit = Some((5, 5));
+        for obs in [&mut ahead, &mut under] {
+            assert_eq!(GreedyAgent::default().decide(obs).name(), "FW");
+            let plain = FloodFillAgent.decide(obs).name();
+            obs.eat_requires_action = true;
+            assert_eq!(GreedyAgent::default().decide(obs).name(), "EA");
+            // Flood fill may rather keep space than go straight, only FW turns into EA
+            let eating = if plain == "FW" { "EA" } else { plain };
+            assert_eq!(FloodFillAgent.decide(obs).name(), eating);
+        }
+        // Turning onto the fruit can't eat it on arrival, so the turn is kept
+        let mut aside = observation(vec![(5, 5), (4, 5)], (1, 0));
+        aside.fruit = Some((5, 6));
+        aside.eat_requires_action = true;
+        assert_eq!(GreedyAgent::default().decide(&aside).name(), "RT");
+    }
+
+    #[test]
+    fn cache_hit_brings_back_the_plan_for_that_state() {
+        let mut agent = CachedAgent::new(Box::new(GreedyAgent::default()), 8);
+        let mut first = observation(vec![(5, 5), (4, 5)], (1, 0));
+        first.fruit = Some((8, 5));
+        let mut second = observation(vec![(2, 2), (1, 2)], (1, 0));
+        second.fruit = Some((2, 7));
+
+        agent.decide(&first);
+        let first_path = agent.planned_path().unwrap().to_vec();
+        agent.decide(&second);
+        assert_eq!(agent.planned_path().unwrap().last(), Some(&(2, 7)));
+        agent.decide(&first);
+        assert_eq!(agent.planned_path().unwrap(), first_path);
+    }
+}
@@ -0,0 +1,53 @@
+// Challenge of the day: everyone playing on the same UTC date gets the same seed
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Days since 1970-01-01 in UTC
+pub fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() / 86_400)
+}
+
+// Scrambled so neighbouring days don't get neighbouring seeds (splitmix64 finalizer)
+pub fn seed_for(day: u64) -> u64 {
+    let mut z = day.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// Calendar date of a day number, as "YYYY-MM-DD"
+pub fn date_name(day: u64) -> String {
+    // Days counted from 0000-03-01 so the leap day falls at the end of each year
+    let z = day as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_day_gives_the_same_seed() {
+        assert_eq!(seed_for(20_741), seed_for(20_741));
+        assert_ne!(seed_for(20_741), seed_for(20_742));
+    }
+
+    #[test]
+    fn day_numbers_name_calendar_dates() {
+        assert_eq!(date_name(0), "1970-01-01");
+        assert_eq!(date_name(59), "1970-03-01");
+        assert_eq!(date_name(11_016), "2000-02-29");
+        assert_eq!(date_name(20_741), "2026-10-15");
+    }
+}
@@ -0,0 +1,39 @@
+// Curated scenarios for reproducible showcase runs, each one a known-good seed
+pub struct Demo {
+    pub name: &'static str,
+    pub seed: u64,
+    pub board_size: (isize, isize),
+    pub agent: &'static str,
+    pub start_length: usize,
+}
+
+pub const DEMOS: &[Demo] = &[
+    // Flood fill agent filling most of the default board
+    Demo {
+        name: "long-survival",
+        seed: 7,
+        board_size: (16, 16),
+        agent: "floodfill",
+        start_length: 1,
+    },
+    // Greedy agent chasing fruit until it boxes itself in
+    Demo {
+        name: "greedy-trap",
+        seed: 11,
+        board_size: (16, 16),
+        agent: "greedy",
+        start_length: 1,
+    },
+    // Cramped board where every move matters
+    Demo {
+        name: "small-board",
+        seed: 3,
+        board_size: (8, 8),
+        agent: "floodfill",
+        start_length: 3,
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Demo> {
+    DEMOS.iter().find(|demo| demo.name == name)
+}
@@ -0,0 +1,130 @@
+// Offline rendering of a replay to numbered PNG frames for making videos
+// Frames are drawn on the CPU into images, which unlike the window's GPU context
+// can be shared out between threads
+
+use crate::replay::Snapshot;
+use crate::theme::Theme;
+use crate::SQUARE_SIZE;
+use raylib::prelude::*;
+
+// Steps per second when a replay plays in the window: one every 10 frames at 60 fps
+pub const STEP_RATE: usize = 6;
+
+pub fn frame_count(steps: usize, fps: usize) -> usize {
+    steps * fps / STEP_RATE
+}
+
+// Draws the states of a replayed run, one per step, to `out_dir/frame_NNNNNN.png` at
+// `fps`, sliding the snake between steps
+// Frames already on disk are skipped, so an interrupted render picks up where it stopped
+// Returns how many frames were written
+pub fn render_replay_to_frames(
+    states: &[Snapshot],
+    board_size: (isize, isize),
+    theme: &Theme,
+    out_dir: &str,
+    fps: usize,
+) -> std::io::Result<usize> {
+    std::fs::create_dir_all(out_dir)?;
+    let frames = frame_count(states.len().saturating_sub(1), fps);
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|worker| {
+                scope.spawn(move || {
+                    let mut written = 0;
+                    for frame in (worker..frames).step_by(workers) {
+                        let path = format!("{}/frame_{:06}.png", out_dir, frame);
+                        if std::path::Path::new(&path).exists() {
+                            continue;
+                        }
+                        // Position between two steps, as a step index and how far along it is
+                        let at = (frame * STEP_RATE) as f32 / fps as f32;
+                        let step = at as usize;
+                        let image = draw_frame(
+                            &states[step],
+                            &states[step + 1],
+                            at - step as f32,
+                            board_size,
+                            theme,
+                        );
+                        image.export_image(&path);
+                        if !std::path::Path::new(&path).exists() {
+                            return Err(std::io::Error::other(format!("could not write {}", path)));
+                        }
+                        written += 1;
+                    }
+                    Ok(written)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("frame worker panicked"))
+            .sum()
+    })
+}
+
+fn draw_frame(
+    from: &Snapshot,
+    to: &Snapshot,
+    t: f32,
+    board_size: (isize, isize),
+    theme: &Theme,
+) -> Image {
+    let mut image = Image::gen_image_color(
+        (board_size.0 * SQUARE_SIZE) as i32,
+        (board_size.1 * SQUARE_SIZE) as i32,
+        theme.background,
+    );
+    if let Some((x, y)) = from.fruit {
+        let corner = ((x * SQUARE_SIZE) as i32, (y * SQUARE_SIZE) as i32);
+        image.draw_rectangle(
+            corner.0,
+            corner.1,
+            SQUARE_SIZE as i32,
+            SQUARE_SIZE as i32,
+            theme.fruit,
+        );
+    }
+    // Tail first so the head ends up on top
+    let len = to.snake.len();
+    for (idx, &cell) in to.snake.iter().enumerate().rev() {
+        let start = from.snake.get(idx).copied().unwrap_or(cell);
+        // Jumps across a wrap edge or a bounce aren't slid, the segment just appears there
+        let adjacent = (cell.0 - start.0).abs() + (cell.1 - start.1).abs() <= 1;
+        let (x, y) = if adjacent {
+            (
+                start.0 as f32 + (cell.0 - start.0) as f32 * t,
+                start.1 as f32 + (cell.1 - start.1) as f32 * t,
+            )
+        } else {
+            (cell.0 as f32, cell.1 as f32)
+        };
+        image.draw_rectangle(
+            (x * SQUARE_SIZE as f32) as i32,
+            (y * SQUARE_SIZE as f32) as i32,
+            SQUARE_SIZE as i32,
+            SQUARE_SIZE as i32,
+            theme.segment_color(idx, len),
+        );
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_count_follows_the_step_rate() {
+        assert_eq!(frame_count(10, 60), 100);
+        assert_eq!(frame_count(10, 30), 50);
+        assert_eq!(frame_count(0, 60), 0);
+        // Every frame lands between two recorded states
+        for fps in [24, 30, 60] {
+            let frames = frame_count(7, fps);
+            assert!((0..frames).all(|frame| frame * STEP_RATE / fps < 7));
+        }
+    }
+}
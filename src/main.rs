@@ -1,11 +1,32 @@
 use rand::prelude::*;
 use raylib::prelude::*;
 
+mod agent;
+mod daily;
+mod demo;
+mod frames;
+mod replay;
+mod rng;
+mod theme;
+use agent::{Agent, CachedAgent, FloodFillAgent, GreedyAgent, Observation};
+use replay::{fold_digest, load_game, save_game, Divergence, Replay, Snapshot, DIGEST_SEED};
+use rng::{GameRng, RngBackend};
+use theme::Theme;
+
 // The following snake game implementation
 // Is based on official raylib example
 // Original code at: https://github.com/raysan5/raylib-games/blob/master/classics/src/snake.c
 const SNAKE_LEN: usize = 256;
 const SQUARE_SIZE: isize = 31;
+const INDEX_FONT_SIZE: i32 = 10;
+// How long the cell the head wrapped onto stays highlighted, and how thick the seams are
+const WRAP_FLASH_FRAMES: usize = 20;
+const SEAM_WIDTH: f32 = 3.0;
+// Room under the board kept for the score line
+const HUD_HEIGHT: isize = 40;
+const FRUIT_REWARD: isize = 1;
+const PULSE_FRAMES: usize = 12;
+const PULSE_EXTRA_SIZE: i32 = 10;
 
 #[derive(Default, Copy, Clone)]
 struct Snake {
@@ -21,21 +42,520 @@ struct Food {
     color: Color,
 }
 
+// Moves are relative to the current heading
+#[derive(Clone, Copy)]
 enum Move {
     FW,
     BW,
     LT,
     RT,
     PS,
+    // Keeps going straight and consumes a fruit under the head when eating needs an action
+    EA,
+}
+
+impl Move {
+    fn name(&self) -> &'static str {
+        match self {
+            Move::FW => "FW",
+            Move::BW => "BW",
+            Move::LT => "LT",
+            Move::RT => "RT",
+            Move::PS => "PS",
+            Move::EA => "EA",
+        }
+    }
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "FW" => Some(Move::FW),
+            "BW" => Some(Move::BW),
+            "LT" => Some(Move::LT),
+            "RT" => Some(Move::RT),
+            "PS" => Some(Move::PS),
+            "EA" => Some(Move::EA),
+            _ => None,
+        }
+    }
 }
+
 enum Mode {
     // Window and Raylib stuff
     Keyboard,
     // Interfacing with external controls
     External {
         moves: std::sync::mpsc::Receiver<Move>,
-        reward: std::sync::mpsc::Sender<isize>,
+        // Raw reward alongside the normalized one, equal to it unless normalizing
+        reward: std::sync::mpsc::Sender<(isize, f64)>,
     },
+    // Driven by an in-process agent, with or without a window
+    Agent(Box<dyn Agent>),
+    // Plays back a recorded run, `cursor` being the next step to apply
+    Replay {
+        replay: Replay,
+        cursor: usize,
+    },
+}
+
+// Font size shrinking linearly back to `base` as the pulse runs out
+fn pulse_font_size(base: i32, frames_left: usize) -> i32 {
+    base + PULSE_EXTRA_SIZE * frames_left.min(PULSE_FRAMES) as i32 / PULSE_FRAMES as i32
+}
+
+// Headless runs with a preview window draw only every `every`th step, 0 meaning never
+fn should_preview(step: usize, every: usize) -> bool {
+    every > 0 && step.is_multiple_of(every)
+}
+
+// Opacity of a vacated cell's tint, fading linearly to nothing as its frames run out
+fn fade_alpha(frames_left: usize, fade_frames: usize) -> f32 {
+    if fade_frames == 0 {
+        return 0.0;
+    }
+    frames_left.min(fade_frames) as f32 / fade_frames as f32
+}
+
+// Camera offset and zoom that scale the board and the HUD strip below it to fit the
+// screen, centered along whichever axis has room to spare
+fn fit_board(screen: (i32, i32), board_size: (isize, isize)) -> (Vector2, f32) {
+    let width = (board_size.0 * SQUARE_SIZE) as f32;
+    let height = (board_size.1 * SQUARE_SIZE + HUD_HEIGHT) as f32;
+    let zoom = (screen.0 as f32 / width).min(screen.1 as f32 / height);
+    if zoom <= 0.0 {
+        return (Vector2 { x: 0.0, y: 0.0 }, 1.0);
+    }
+    let offset = Vector2 {
+        x: (screen.0 as f32 - width * zoom) / 2.0,
+        y: (screen.1 as f32 - height * zoom) / 2.0,
+    };
+    (offset, zoom)
+}
+
+// Top-left corner for text of the given size to sit in the middle of a
+// width by height area starting at the origin
+fn center_text(width: i32, height: i32, text_width: i32, font_size: i32) -> (i32, i32) {
+    ((width - text_width) / 2, (height - font_size) / 2)
+}
+
+// Top-left corner for text of the given size to sit in the middle of a square cell
+fn centered_text(
+    cell_x: i32,
+    cell_y: i32,
+    cell_size: i32,
+    text_width: i32,
+    font_size: i32,
+) -> (i32, i32) {
+    let (x, y) = center_text(cell_size, cell_size, text_width, font_size);
+    (cell_x + x, cell_y + y)
+}
+
+// Where the label for a board column or row starts, columns numbered along the top edge
+// and rows along the left edge, tucked into opposite corners so the first cell holds both
+fn axis_label_position(index: isize, column: bool) -> (i32, i32) {
+    let margin = 2;
+    let cell = (index * SQUARE_SIZE) as i32;
+    if column {
+        (cell + margin, margin)
+    } else {
+        (margin, cell + SQUARE_SIZE as i32 - INDEX_FONT_SIZE - margin)
+    }
+}
+
+// Due once the interval has passed while playing, and once more as soon as the game ends,
+// so the save left behind by a lost game isn't offered for resuming on the next launch
+fn autosave_due(
+    elapsed: f64,
+    every: f64,
+    paused: bool,
+    game_over: bool,
+    saved_game_over: bool,
+) -> bool {
+    if game_over {
+        return !saved_game_over;
+    }
+    !paused && elapsed >= every
+}
+
+// Number of the frame about to be played, None while paused, so a game started paused
+// stays put until it's let go and then picks up from frame 0
+fn next_frame(paused: bool, frames_counter: &mut usize) -> Option<usize> {
+    if paused {
+        return None;
+    }
+    *frames_counter += 1;
+    Some(*frames_counter - 1)
+}
+
+// Next movement key, either read from Raylib or re-injected from a recorded input log
+fn pressed_key(window: &mut WindowData, frame: usize) -> Option<KeyboardKey> {
+    let key = match &mut window.input_playback {
+        Some(events) => next_logged_key(events, frame),
+        None => window.handle.get_key_pressed(),
+    };
+    if let (Some(log), Some(key)) = (&mut window.input_log, key) {
+        if key_name(key).is_some() || key == window.noop_key {
+            log.push((frame, key));
+        }
+    }
+    key
+}
+
+// Whether the eat key is held this frame, read from Raylib or from a recorded input log
+// It's read before the movement key, so a frame's EAT line comes first in the log
+fn held_eat(window: &mut WindowData, frame: usize) -> bool {
+    let held = match &mut window.input_playback {
+        Some(events) => next_logged_eat(events, frame),
+        None => window.handle.is_key_down(KeyboardKey::KEY_E),
+    };
+    if let (Some(log), true) = (&mut window.input_log, held) {
+        log.push((frame, KeyboardKey::KEY_E));
+    }
+    held
+}
+
+// Takes a recorded eat key for exactly this frame off the front of the log
+fn next_logged_eat(
+    events: &mut std::collections::VecDeque<(usize, KeyboardKey)>,
+    frame: usize,
+) -> bool {
+    let held = events.front() == Some(&(frame, KeyboardKey::KEY_E));
+    if held {
+        events.pop_front();
+    }
+    held
+}
+
+// Recorded key due by the given frame, taken off the front of the log
+fn next_logged_key(
+    events: &mut std::collections::VecDeque<(usize, KeyboardKey)>,
+    frame: usize,
+) -> Option<KeyboardKey> {
+    match events.front() {
+        Some(&(at, key)) if at <= frame => {
+            events.pop_front();
+            Some(key)
+        }
+        _ => None,
+    }
+}
+
+// Only the movement keys matter to the game logic as presses, so only those get recorded
+// Holding the eat key is logged separately, see held_eat
+fn key_name(key: KeyboardKey) -> Option<&'static str> {
+    match key {
+        KeyboardKey::KEY_RIGHT => Some("RIGHT"),
+        KeyboardKey::KEY_LEFT => Some("LEFT"),
+        KeyboardKey::KEY_DOWN => Some("DOWN"),
+        KeyboardKey::KEY_UP => Some("UP"),
+        _ => None,
+    }
+}
+
+fn key_from_name(name: &str) -> Option<KeyboardKey> {
+    match name {
+        "RIGHT" => Some(KeyboardKey::KEY_RIGHT),
+        "LEFT" => Some(KeyboardKey::KEY_LEFT),
+        "DOWN" => Some(KeyboardKey::KEY_DOWN),
+        "UP" => Some(KeyboardKey::KEY_UP),
+        _ => None,
+    }
+}
+
+// One "<frame> <key>" line per recorded key press, the no-op key written as NOOP
+// whichever key it is bound to, and one EAT line for every frame the eat key is held
+fn save_input_log(
+    path: &str,
+    log: &[(usize, KeyboardKey)],
+    noop_key: KeyboardKey,
+) -> std::io::Result<()> {
+    let text: String = log
+        .iter()
+        .filter_map(|&(frame, key)| {
+            let name = if key == noop_key {
+                "NOOP"
+            } else if key == KeyboardKey::KEY_E {
+                "EAT"
+            } else {
+                key_name(key)?
+            };
+            Some(format!("{} {}\n", frame, name))
+        })
+        .collect();
+    std::fs::write(path, text)
+}
+
+fn load_input_log(
+    path: &str,
+    noop_key: KeyboardKey,
+) -> Result<std::collections::VecDeque<(usize, KeyboardKey)>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (frame, key) = line
+                .split_once(' ')
+                .ok_or(format!("Malformed line: {}", line))?;
+            let frame = frame
+                .parse()
+                .map_err(|_| format!("Malformed frame: {}", line))?;
+            let key = match key.trim() {
+                "NOOP" => noop_key,
+                "EAT" => KeyboardKey::KEY_E,
+                name => key_from_name(name).ok_or(format!("Unknown key: {}", line))?,
+            };
+            Ok((frame, key))
+        })
+        .collect()
+}
+
+// Board shape drawn as text, one line per row, with '#' for cells outside the board
+// and anything else for playable ones
+fn load_mask(path: &str) -> Result<((isize, isize), Vec<bool>), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let rows: Vec<&str> = text.lines().filter(|line| !line.is_empty()).collect();
+    let width = rows.first().ok_or("Empty mask")?.chars().count();
+    if rows.iter().any(|row| row.chars().count() != width) {
+        return Err("Mask rows differ in length".to_string());
+    }
+    let mask = rows
+        .iter()
+        .flat_map(|row| row.chars().map(|c| c != '#'))
+        .collect();
+    Ok(((width as isize, rows.len() as isize), mask))
+}
+
+// Decides whether a finished game should start over, called once per frame on the game over screen
+fn should_restart(
+    behavior: &GameOverBehavior,
+    game_over_frames: &mut usize,
+    enter_pressed: bool,
+    in_menu: &mut bool,
+) -> bool {
+    match behavior {
+        GameOverBehavior::WaitForEnter => enter_pressed,
+        GameOverBehavior::AutoRestart { delay_frames } => {
+            auto_restart_due(game_over_frames, *delay_frames)
+        }
+        GameOverBehavior::ReturnToMenu => {
+            *in_menu = true;
+            false
+        }
+    }
+}
+
+// Counts another frame on the game over screen, true once `delay_frames` have gone by
+fn auto_restart_due(game_over_frames: &mut usize, delay_frames: usize) -> bool {
+    *game_over_frames += 1;
+    *game_over_frames >= delay_frames
+}
+
+// Applies an absolute direction to the current velocity
+// Turns along the axis already being travelled (including reversing) are ignored
+fn steer(velocity: (isize, isize), direction: (isize, isize)) -> (isize, isize) {
+    if (direction.0 != 0 && velocity.0 == 0) || (direction.1 != 0 && velocity.1 == 0) {
+        direction
+    } else {
+        velocity
+    }
+}
+
+// Inside the board and not cut out by the mask
+fn cell_playable(board_size: (isize, isize), mask: Option<&[bool]>, cell: (isize, isize)) -> bool {
+    let in_bounds = cell.0 >= 0 && cell.1 >= 0 && cell.0 < board_size.0 && cell.1 < board_size.1;
+    in_bounds && mask.is_none_or(|mask| mask[(cell.1 * board_size.0 + cell.0) as usize])
+}
+
+// Pausing holds every control mode that plays in the window, but a headless run has no
+// window to release it from, so the flag is refused there unless a window attaches later
+fn drop_headless_pause(options: &mut Options) {
+    if options.start_paused && options.headless.is_some() && options.attach_after.is_none() {
+        eprintln!("--start-paused needs a window, ignoring it for --headless");
+        options.start_paused = false;
+    }
+}
+
+// A --board or --demo after --mask or --level resizes the board under the layout they
+// loaded, so a mask that no longer fits is dropped rather than indexed out of bounds,
+// along with a start cell or first fruit left off the board
+fn drop_misfit_mask(config: &mut GameConfig) {
+    let (w, h) = config.board_size.unwrap_or((16, 16));
+    if config
+        .playable_mask
+        .as_ref()
+        .is_some_and(|mask| mask.len() != (w * h) as usize)
+    {
+        eprintln!("Board mask doesn't fit the {}x{} board, ignoring it", w, h);
+        config.playable_mask = None;
+    }
+    let on_board = |cell: &(isize, isize)| cell_playable((w, h), None, *cell);
+    if config.start_cell.take_if(|cell| !on_board(cell)).is_some() {
+        eprintln!("Start cell is off the {}x{} board, ignoring it", w, h);
+    }
+    if config.start_fruit.take_if(|cell| !on_board(cell)).is_some() {
+        eprintln!("First fruit is off the {}x{} board, ignoring it", w, h);
+    }
+}
+
+// Straight snake with the body trailing off to the left, its head at `start` or at the end
+// of the first `length` cells of the first playable run, read row by row
+// The body is cut short rather than run into a wall
+fn initial_snake(
+    length: usize,
+    board_size: (isize, isize),
+    mask: Option<&[bool]>,
+    start: Option<(isize, isize)>,
+) -> Vec<(isize, isize)> {
+    match start {
+        Some((x, y)) => (0..length.max(1) as isize)
+            .map(|i| (x - i, y))
+            .take_while(|&cell| cell == (x, y) || cell_playable(board_size, mask, cell))
+            .collect(),
+        None => {
+            let (w, h) = board_size;
+            let tail = (0..h)
+                .flat_map(|y| (0..w).map(move |x| (x, y)))
+                .find(|&cell| cell_playable(board_size, mask, cell))
+                .unwrap_or((0, 0));
+            let mut snake: Vec<_> = (0..length.max(1) as isize)
+                .map(|i| (tail.0 + i, tail.1))
+                .take_while(|&cell| cell == tail || cell_playable(board_size, mask, cell))
+                .collect();
+            snake.reverse();
+            snake
+        }
+    }
+}
+
+// Board laid out in an image, one pixel per cell
+struct Level {
+    board_size: (isize, isize),
+    mask: Vec<bool>,
+    start: (isize, isize),
+    fruit: Option<(isize, isize)>,
+}
+
+fn load_level_image(path: &str) -> Result<Level, String> {
+    let image = Image::load_image(path)?;
+    decode_level(image.width() as isize, &image.get_image_data())
+}
+
+// Black pixels are walls, blue marks the single start cell, green an optional first
+// fruit, and any other color is open floor. Pixels run row by row, `width` to a row
+fn decode_level(width: isize, pixels: &[Color]) -> Result<Level, String> {
+    let height = pixels.len() as isize / width.max(1);
+    let mut level = Level {
+        board_size: (width, height),
+        mask: Vec::with_capacity((width * height) as usize),
+        start: (0, 0),
+        fruit: None,
+    };
+    let mut starts = 0;
+    for (idx, pixel) in pixels.iter().enumerate() {
+        let cell = (idx as isize % width, idx as isize / width);
+        let dark = |channel: u8| channel < 64;
+        let bright = |channel: u8| channel >= 192;
+        let wall = dark(pixel.r) && dark(pixel.g) && dark(pixel.b);
+        if dark(pixel.r) && dark(pixel.g) && bright(pixel.b) {
+            level.start = cell;
+            starts += 1;
+        } else if dark(pixel.r) && bright(pixel.g) && dark(pixel.b) {
+            level.fruit = Some(cell);
+        }
+        level.mask.push(!wall);
+    }
+    if starts != 1 {
+        return Err(format!("Expected one start cell, found {}", starts));
+    }
+    Ok(level)
+}
+
+// Single entry point for direction changes from every control mode
+// Only the first turn that actually changes the heading counts until the snake moves,
+// so a burst of inputs can't fold the snake back into its neck
+fn turn(velocity: &mut (isize, isize), turned_this_step: &mut bool, direction: (isize, isize)) {
+    if *turned_this_step {
+        return;
+    }
+    let steered = steer(*velocity, direction);
+    if steered != *velocity {
+        *velocity = steered;
+        *turned_this_step = true;
+    }
+}
+
+// Notable moments in a game, for anything that wants to react to them
+enum GameEvent {
+    Ate,
+    Died,
+    // Tail moved off this cell
+    Vacated((isize, isize)),
+    // Head crossed a board edge in wrap mode, coming back in on this cell
+    Wrapped((isize, isize)),
+}
+
+// What occupies a cell, for renderers working from a sparse list of cells
+#[derive(Clone, Copy, PartialEq)]
+enum CellKind {
+    Head,
+    Body,
+    Fruit,
+    ShrinkPowerup,
+}
+
+// Draws a list of occupied cells in order, so later entries end up on top
+// Snake segments are expected tail first, as their color depends on the position
+fn draw_cells(context: &mut impl RaylibDraw, cells: &[(isize, isize, CellKind)], theme: &Theme) {
+    let snake_len = cells
+        .iter()
+        .filter(|(_, _, kind)| matches!(kind, CellKind::Head | CellKind::Body))
+        .count();
+    let cell_size = SQUARE_SIZE as f32;
+    let mut segment = 0;
+    for &(x, y, kind) in cells {
+        let corner = Vector2 {
+            x: (x * SQUARE_SIZE) as f32,
+            y: (y * SQUARE_SIZE) as f32,
+        };
+        let center = Vector2 {
+            x: corner.x + cell_size / 2.0,
+            y: corner.y + cell_size / 2.0,
+        };
+        match kind {
+            CellKind::Head | CellKind::Body => {
+                context.draw_rectangle_v(
+                    corner,
+                    Vector2 {
+                        x: cell_size,
+                        y: cell_size,
+                    },
+                    theme.segment_color(snake_len - 1 - segment, snake_len),
+                );
+                segment += 1;
+            }
+            CellKind::ShrinkPowerup => context.draw_rectangle_v(
+                Vector2 {
+                    x: corner.x + cell_size / 4.0,
+                    y: corner.y + cell_size / 4.0,
+                },
+                Vector2 {
+                    x: cell_size / 2.0,
+                    y: cell_size / 2.0,
+                },
+                theme.powerup,
+            ),
+            CellKind::Fruit if theme.round_fruit => {
+                context.draw_circle_v(center, cell_size / 2.0, theme.text);
+                context.draw_circle_v(center, cell_size / 2.0 - 3.0, theme.fruit);
+            }
+            CellKind::Fruit => context.draw_rectangle_v(
+                corner,
+                Vector2 {
+                    x: cell_size,
+                    y: cell_size,
+                },
+                theme.fruit,
+            ),
+        }
+    }
 }
 
 struct WindowData<'a> {
@@ -44,12 +564,230 @@ struct WindowData<'a> {
     frames_counter: usize,
 
     allow_move: bool,
+    // Board placement on screen, refreshed every frame while fitting to the window
     offset: Vector2,
+    zoom: f32,
+    fit_to_window: bool,
+    // Headless runs draw to this window every this many steps, 0 when not previewing
+    preview_every: usize,
     pause: bool,
+    // Debug overlay numbering every snake segment
+    show_indices: bool,
+    // Column and row numbers along the board edges
+    show_axes: bool,
+    // Debug overlay of the route the agent intends to take
+    show_path: bool,
+    // Frames spent on the game over screen, used by auto restart
+    game_over_frames: usize,
+    in_menu: bool,
+    theme: Theme,
+    // Raw movement key presses with the frame they landed on, recorded only when enabled
+    input_log: Option<Vec<(usize, KeyboardKey)>>,
+    // Explicit "keep going straight" input, logged so it differs from no input at all
+    noop_key: KeyboardKey,
+    // Recorded key presses fed back in place of the keyboard
+    input_playback: Option<std::collections::VecDeque<(usize, KeyboardKey)>>,
+    // Frames left of the score enlargement after eating
+    score_pulse: usize,
+    // How many frames cells the snake left stay tinted, 0 turns the trail off
+    trail_frames: usize,
+    // Wrap mode edges are marked, and the entry cell flashes after each wrap
+    show_seams: bool,
+    wrap_flash: Option<((isize, isize), usize)>,
+    // HUD shows the score out of the best one the board allows
+    show_max_score: bool,
+    // Playing today's shared seed, marked on the HUD
+    daily: bool,
+    // Recently vacated cells with the frames left until they're fully faded
+    trail: Vec<((isize, isize), usize)>,
+    // Game in progress is written here every `autosave_every` seconds
+    autosave: Option<String>,
+    autosave_every: f64,
+    last_autosave: f64,
+    // The finished game has been saved already, so it isn't written every frame
+    autosaved_game_over: bool,
+    // Unfinished game found in the autosave at launch, resumed with L
+    resume_offer: Option<Snapshot>,
+}
+
+impl<'a> WindowData<'a> {
+    fn new(handle: &'a mut RaylibHandle, thread: &'a mut RaylibThread) -> Self {
+        Self {
+            handle,
+            thread,
+            frames_counter: 0,
+            allow_move: false,
+            offset: Vector2 { x: 0.0, y: 0.0 },
+            zoom: 1.0,
+            fit_to_window: false,
+            preview_every: 0,
+            pause: false,
+            show_indices: false,
+            show_axes: false,
+            show_path: false,
+            game_over_frames: 0,
+            in_menu: false,
+            theme: Theme::default(),
+            input_log: None,
+            noop_key: KeyboardKey::KEY_PERIOD,
+            input_playback: None,
+            score_pulse: 0,
+            trail_frames: 0,
+            show_max_score: false,
+            daily: false,
+            show_seams: false,
+            wrap_flash: None,
+            trail: Vec::new(),
+            autosave: None,
+            autosave_every: 30.0,
+            last_autosave: 0.0,
+            autosaved_game_over: false,
+            resume_offer: None,
+        }
+    }
+}
+
+// Where new fruit prefers to appear
+#[derive(Default, Clone, Copy, PartialEq)]
+enum SpawnBias {
+    #[default]
+    Uniform,
+    // Favors cells near the middle of the board, making the game easier
+    Center,
+    // Favors cells near the walls, making the game harder
+    Edge,
+}
+
+// End of the snake that receives the new segment after eating
+#[derive(Default, Clone, Copy, PartialEq)]
+enum GrowthSide {
+    #[default]
+    Tail,
+    // The head lurches one cell forward, so the snake keeps index 0 as its head
+    Head,
+}
+
+// What happens when the head runs into the edge of the board
+#[derive(Default, Clone, Copy, PartialEq)]
+enum WallBehavior {
+    #[default]
+    Solid,
+    // Leaving the board re-enters on the opposite side instead of ending the game
+    Wrap,
+    // The snake turns around and carries on from its tail, since heading straight back
+    // would run into the neck
+    Bounce,
+}
+
+// Mean and variance of every reward sent so far, updated one value at a time (Welford)
+#[derive(Default)]
+struct RunningStats {
+    count: usize,
+    mean: f64,
+    // Sum of squared differences from the mean
+    m2: f64,
+}
+
+impl RunningStats {
+    fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+    // How many deviations `value` sits from the mean, 0 for a constant stream
+    fn normalize(&self, value: f64) -> f64 {
+        let std = (self.m2 / self.count.max(1) as f64).sqrt();
+        (value - self.mean) / (std + 1e-8)
+    }
+}
+
+// Dying rewinds to the last meal while lives remain
+#[derive(Clone)]
+struct PracticeConfig {
+    lives: usize,
+}
+
+// What the window does once the snake dies
+#[derive(Default, Clone)]
+enum GameOverBehavior {
+    #[default]
+    WaitForEnter,
+    AutoRestart {
+        delay_frames: usize,
+    },
+    ReturnToMenu,
+}
+
+// Gameplay variants, all off by default to match the original rules
+#[derive(Default, Clone)]
+struct GameConfig {
+    // Eating queues a segment that appears at the tail on following steps
+    delayed_growth: bool,
+    // Fruit is only eaten on the eat key (keyboard) or the EA move (agents, external)
+    eat_requires_action: bool,
+    spawn_bias: SpawnBias,
+    // Only used with instant growth, delayed growth always extends the tail
+    growth_side: GrowthSide,
+    walls: WallBehavior,
+    practice: Option<PracticeConfig>,
+    // Never spawn fruit on the cell the head enters next step
+    spawn_protection: bool,
+    // Fruit cut off from the head by the body is moved somewhere reachable
+    relocate_unreachable_fruit: bool,
+    rng: RngBackend,
+    // Points for every row or column the body fills completely, at most once per line
+    line_bonus: isize,
+    game_over: GameOverBehavior,
+    // Points for visiting every cell, paid out proportionally to coverage
+    coverage_bonus: isize,
+    // Points for the first visit to each cell, halving every `exploration_half_life` steps
+    // so early exploration pays the most, 0 half-life meaning no decay
+    exploration_bonus: isize,
+    exploration_half_life: usize,
+    // Pure survival: no fruit ever spawns and every step survived scores a point
+    disable_fruit: bool,
+    // Segments the snake starts with, useful when it can't grow
+    start_length: usize,
+    // Width and height in cells, 16x16 when unset
+    board_size: Option<(isize, isize)>,
+    // Row-major board shape, cells marked false act as walls and aren't drawn
+    playable_mask: Option<Vec<bool>>,
+    // Where the head starts and the first fruit lies, set by loaded levels
+    start_cell: Option<(isize, isize)>,
+    start_fruit: Option<(isize, isize)>,
+    // Segments removed by a shrink power-up, which only appear when this is non-zero
+    shrink_amount: usize,
+    // The tail loses a segment every this many steps, down to the head alone
+    tail_decay_interval: Option<usize>,
+    // Agents are asked for a move every this many steps, holding their heading in between
+    action_repeat: usize,
+    // Agents only see this far around the head in their observation grid
+    fog_radius: Option<isize>,
+    // Extra lives that respawn a one-segment snake near the middle, unless practice mode
+    // already uses lives for rewinding
+    respawn_lives: usize,
+    // A keyboard player's death hands the snake to the built-in agent instead
+    coop_takeover: bool,
+    // Agents act on the state from this many steps ago, simulating input lag
+    observation_delay: usize,
+    // Rewards sent to an external controller are clamped to plus or minus this much,
+    // the score itself is left alone
+    reward_clip: Option<isize>,
+    // Rewards are also sent scaled by running statistics kept across games
+    normalize_rewards: bool,
+    // Reaching either of these ends the game as solved rather than lost
+    score_cap: Option<isize>,
+    length_cap: Option<usize>,
+    // Start off in a random safe direction instead of always heading right
+    random_start_direction: bool,
+    // Collisions during this many first steps are ignored
+    spawn_grace_steps: usize,
 }
 
 struct GameState<'a> {
     control_mode: Mode,
+    config: GameConfig,
     window: Option<WindowData<'a>>,
 
     // Representation of game (environment)
@@ -59,26 +797,68 @@ struct GameState<'a> {
     fruit_position: Option<(isize, isize)>,
     snake_position: Vec<(isize, isize)>,
     snake_velocity: (isize, isize),
+    turned_this_step: bool,
+    // Set by the eat key or action, only consulted when eating requires one
+    eat_requested: bool,
+    // Steps left to hold the last agent decision, and the reward collected meanwhile
+    repeat_left: usize,
+    repeat_reward: isize,
+    reward_stats: RunningStats,
+    // Latest observations, oldest first, for delaying what the agent sees
+    observations: std::collections::VecDeque<Observation>,
     counter_tail: isize,
     board_size: (isize, isize),
+    pending_growth: usize,
+    rng: GameRng,
+    score: isize,
+    // Rows and columns that already paid out the line bonus
+    credited_lines: (Vec<bool>, Vec<bool>),
+    // Cells the head has been on this game, indexed as [y][x]
+    visited: Vec<Vec<bool>>,
+    coverage_awarded: isize,
+    // Points earned from the exploration bonus this game
+    exploration_score: isize,
+    // Fruit put on the board and fruit eaten this game, a level's first fruit included
+    fruits_spawned: usize,
+    fruits_eaten: usize,
+    // Cells that cut the tail short when eaten
+    shrink_powerups: Vec<(isize, isize)>,
+    // Lives left for practice rewinds or arcade respawns, the checkpoint being the state
+    // right after the last meal
+    lives: usize,
+    checkpoint: Option<Snapshot>,
+
+    // Every spawned fruit position, recorded only when enabled
+    fruit_log: Option<Vec<(isize, isize)>>,
+    // Moves and keyframes of agent driven runs, recorded only when enabled
+    recording: Option<Replay>,
+    // Wall-clock time of every agent decision, recorded only when enabled
+    decision_latency: Option<Vec<std::time::Duration>>,
+    // One JSON line per agent decision, recorded only when enabled
+    decision_log: Option<Vec<String>>,
+    // Total time spent in each part of a step, recorded only when profiling
+    profile: Option<Vec<(&'static str, std::time::Duration)>>,
+    // Things that happened since the last frame was drawn
+    events: Vec<GameEvent>,
+    // The agent is flying the player's snake after a co-op takeover
+    rescuing: bool,
+    // Move an outside controller sent ahead of the step that uses it, see external_move_ready
+    next_external_move: Option<Move>,
+    // The game ended by hitting the score or length cap, not by dying
+    solved: bool,
+    // Moves made since the game started, for the grace period
+    steps_taken: usize,
 }
 
 impl<'a> GameState<'a> {
-    fn init(with_window: Option<(&'a mut RaylibHandle, &'a mut RaylibThread)>) -> Self {
-        let window = if let Some((h, t)) = with_window {
-            Some(WindowData {
-                handle: h,
-                thread: t,
-                frames_counter: 0,
-                allow_move: false,
-                offset: Vector2 { x: 0.0, y: 0.0 },
-                pause: false,
-            })
-        } else {
-            None
-        };
+    fn init(
+        with_window: Option<(&'a mut RaylibHandle, &'a mut RaylibThread)>,
+        config: GameConfig,
+    ) -> Self {
+        let window = with_window.map(|(h, t)| WindowData::new(h, t));
 
-        Self {
+        let board_size = config.board_size.unwrap_or((16, 16));
+        let mut game_state = Self {
             window,
             control_mode: Mode::Keyboard,
 
@@ -86,234 +866,3943 @@ impl<'a> GameState<'a> {
 
             counter_tail: 1,
 
-            snake_position: vec![(0, 0)],
-            fruit_position: None,
+            snake_position: initial_snake(
+                config.start_length,
+                board_size,
+                config.playable_mask.as_deref(),
+                config.start_cell,
+            ),
+            fruit_position: config.start_fruit,
             snake_velocity: (1, 0),
-            board_size: (16, 16),
+            turned_this_step: false,
+            eat_requested: false,
+            repeat_left: 0,
+            repeat_reward: 0,
+            reward_stats: RunningStats::default(),
+            observations: std::collections::VecDeque::new(),
+            board_size,
+            pending_growth: 0,
+            rng: config.rng.build(),
+            score: 0,
+            credited_lines: (
+                vec![false; board_size.1 as usize],
+                vec![false; board_size.0 as usize],
+            ),
+            visited: vec![vec![false; board_size.0 as usize]; board_size.1 as usize],
+            coverage_awarded: 0,
+            exploration_score: 0,
+            fruits_spawned: config.start_fruit.is_some() as usize,
+            fruits_eaten: 0,
+            shrink_powerups: Vec::new(),
+            lives: config
+                .practice
+                .as_ref()
+                .map_or(config.respawn_lives, |p| p.lives),
+            checkpoint: None,
+
+            fruit_log: None,
+            recording: None,
+            decision_latency: None,
+            profile: None,
+            decision_log: None,
+            events: Vec::new(),
+            rescuing: false,
+            next_external_move: None,
+            solved: false,
+            steps_taken: 0,
+
+            config,
+        };
+        game_state.snake_velocity = game_state.start_velocity();
+        game_state.visit_start();
+        if game_state.config.practice.is_some() {
+            game_state.checkpoint = Some(game_state.snapshot());
         }
+        game_state
+    }
+    // Heading that neither leaves the board nor turns back into the neck on the first step,
+    // picked at random when asked for and otherwise right whenever that's open
+    fn start_velocity(&mut self) -> (isize, isize) {
+        let head = self.snake_position[0];
+        let neck = self.snake_position.get(1).copied();
+        let headings: Vec<_> = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+            .into_iter()
+            .filter(|&(dx, dy)| {
+                let next = (head.0 + dx, head.1 + dy);
+                self.is_playable(next) && Some(next) != neck
+            })
+            .collect();
+        let heading = if self.config.random_start_direction {
+            headings.choose(&mut self.rng)
+        } else {
+            headings.first()
+        };
+        heading.copied().unwrap_or((1, 0))
     }
     fn reset(&mut self) {
         self.game_over = false;
+        self.solved = false;
+        self.steps_taken = 0;
         self.counter_tail = 1;
-        self.snake_position = vec![(0, 0)];
-        self.fruit_position = None;
-        self.snake_velocity = (1, 0);
+        self.snake_position = initial_snake(
+            self.config.start_length,
+            self.board_size,
+            self.config.playable_mask.as_deref(),
+            self.config.start_cell,
+        );
+        self.fruit_position = self.config.start_fruit;
+        self.snake_velocity = self.start_velocity();
+        self.turned_this_step = false;
+        self.repeat_left = 0;
+        self.repeat_reward = 0;
+        self.observations.clear();
+        self.pending_growth = 0;
+        self.score = 0;
+        self.credited_lines = (
+            vec![false; self.board_size.1 as usize],
+            vec![false; self.board_size.0 as usize],
+        );
+        self.visited = vec![vec![false; self.board_size.0 as usize]; self.board_size.1 as usize];
+        self.visit_start();
+        self.exploration_score = 0;
+        self.fruits_spawned = self.config.start_fruit.is_some() as usize;
+        self.fruits_eaten = 0;
+        self.lives = self
+            .config
+            .practice
+            .as_ref()
+            .map_or(self.config.respawn_lives, |p| p.lives);
+        self.shrink_powerups.clear();
+        // A recording covers a single game, so the new one starts from scratch
+        if let Some(recording) = &mut self.recording {
+            *recording = Replay::new(recording.interval);
+        }
+        if let Some(window) = &mut self.window {
+            window.autosaved_game_over = false;
+            window.game_over_frames = 0;
+            window.trail.clear();
+        }
+        if let Mode::Replay { cursor, .. } = &mut self.control_mode {
+            *cursor = 0;
+        }
+        if self.rescuing {
+            self.hand_back();
+        }
+        // A death before the first meal rewinds to the start
+        self.checkpoint = self.config.practice.is_some().then(|| self.snapshot());
     }
     fn update_game(&mut self) {
+        if let Some(window) = &mut self.window {
+            if window.in_menu {
+                if window.handle.is_key_pressed(KeyboardKey::KEY_ENTER) {
+                    window.in_menu = false;
+                    self.reset();
+                }
+                return;
+            }
+            // Games started paused wait here, giving time to set up a capture first
+            if window.pause && window.handle.is_key_pressed(KeyboardKey::KEY_SPACE) {
+                window.pause = false;
+                window.resume_offer = None;
+            }
+            if let Some(snapshot) = window.resume_offer.take() {
+                if window.handle.is_key_pressed(KeyboardKey::KEY_L) {
+                    window.pause = false;
+                    self.restore(&snapshot);
+                } else {
+                    window.resume_offer = Some(snapshot);
+                }
+            }
+        }
+        self.autosave();
+
         match self.control_mode {
             Mode::Keyboard => {
                 if let Some(window) = &mut self.window {
                     if !self.game_over {
-                        if !window.pause {
-                            if window.allow_move {
-                                match window.handle.get_key_pressed() {
-                                    Some(KeyboardKey::KEY_RIGHT) => {
-                                        if self.snake_velocity.0 == 0 {
-                                            self.snake_velocity = (1, 0);
-                                        }
-                                        window.allow_move = false;
-                                    }
-                                    Some(KeyboardKey::KEY_LEFT) => {
-                                        if self.snake_velocity.0 == 0 {
-                                            self.snake_velocity = (-1, 0);
-                                        }
-                                        window.allow_move = false;
-                                    }
-                                    Some(KeyboardKey::KEY_DOWN) => {
-                                        if self.snake_velocity.1 == 0 {
-                                            self.snake_velocity = (0, 1);
-                                        }
-                                        window.allow_move = false;
-                                    }
-                                    Some(KeyboardKey::KEY_UP) => {
-                                        if self.snake_velocity.1 == 0 {
-                                            self.snake_velocity = (0, -1);
-                                        }
-                                        window.allow_move = false;
-                                    }
-                                    _ => (),
-                                }
-                            }
-
-                            let last_position = self.snake_position.last().unwrap().clone();
-                            if window.frames_counter % 10 == 0 {
-                                let saved_position = self.snake_position[0];
-                                for i in (1..self.snake_position.len()).rev() {
-                                    self.snake_position[i] = self.snake_position[i - 1];
-                                }
-                                self.snake_position[0].0 = saved_position.0 + self.snake_velocity.0;
-                                self.snake_position[0].1 = saved_position.1 + self.snake_velocity.1;
-
-                                window.allow_move = true;
-                            }
-                            window.frames_counter += 1;
-
-                            if self.snake_position[0].0 >= self.board_size.0
-                                || self.snake_position[0].1 >= self.board_size.1
-                                || self.snake_position[0].0 < 0
-                                || self.snake_position[0].1 < 0
-                            {
-                                self.game_over = true;
-                            }
-
-                            for i in 1..self.snake_position.len() {
-                                if self.snake_position[0] == self.snake_position[i] {
-                                    self.game_over = true;
-                                }
-                            }
-
-                            if let None = self.fruit_position {
-                                let mut rng = rand::rng();
-                                let random_position = (
-                                    rng.random_range(0..self.board_size.0 as i64) as isize,
-                                    rng.random_range(0..self.board_size.1 as i64) as isize,
-                                );
-
-                                if self.snake_position.iter().any(|p| *p == random_position) {
-                                    self.fruit_position = Some(last_position);
-                                } else {
-                                    self.fruit_position = Some(random_position);
-                                }
+                        if let Some(frame) = next_frame(window.pause, &mut window.frames_counter) {
+                            let eat_held = held_eat(window, frame);
+                            let key = if window.allow_move {
+                                pressed_key(window, frame)
+                            } else {
+                                None
                             };
-
-                            if let Some(pos) = self.fruit_position {
-                                if self.snake_position[0] == pos {
-                                    self.snake_position.push(last_position);
-                                    self.fruit_position = None;
-                                }
+                            let noop_key = window.noop_key;
+                            let mut allow_move = window.allow_move;
+                            self.keyboard_frame(frame, key, noop_key, eat_held, &mut allow_move);
+                            if let Some(window) = &mut self.window {
+                                window.allow_move = allow_move;
                             }
                         }
-                    } else if window.handle.is_key_pressed(KeyboardKey::KEY_ENTER) {
-                        self.reset();
+                    } else {
+                        self.window_game_over_frame();
                     }
                 }
             }
-            _ => (),
-        }
-    }
-    fn draw_game(&mut self) {
-        match &mut self.window {
-            Some(window) => {
-                let mut context = window.handle.begin_drawing(&window.thread);
-
-                context.clear_background(Color::RAYWHITE);
-                if !self.game_over {
-                    //Grid lines
-                    for i in 0..=self.board_size.0 {
-                        context.draw_line_v(
-                            Vector2 {
-                                x: (SQUARE_SIZE * i) as f32 + window.offset.x / 2.0,
-                                y: window.offset.y / 2.0,
-                            },
-                            Vector2 {
-                                x: (SQUARE_SIZE * i) as f32 + window.offset.x / 2.0,
-                                y: (self.board_size.1 * SQUARE_SIZE) as f32 - window.offset.y / 2.0,
-                            },
-                            Color::LIGHTGRAY,
-                        )
-                    }
-
-                    for i in 0..=self.board_size.1 {
-                        context.draw_line_v(
-                            Vector2 {
-                                x: window.offset.x / 2.0,
-                                y: (SQUARE_SIZE * i) as f32 + window.offset.y / 2.0,
-                            },
-                            Vector2 {
-                                x: (self.board_size.0 * SQUARE_SIZE) as f32 - window.offset.x / 2.0,
-                                y: (SQUARE_SIZE * i) as f32 + window.offset.y / 2.0,
-                            },
-                            Color::LIGHTGRAY,
-                        )
-                    }
-
-                    //Snake
-                    for (idx, snake_segment) in self.snake_position.iter().enumerate() {
-                        context.draw_rectangle_v(
-                            Vector2 {
-                                x: (snake_segment.0 * SQUARE_SIZE) as f32,
-                                y: (snake_segment.1 * SQUARE_SIZE) as f32,
-                            },
-                            Vector2 {
-                                x: SQUARE_SIZE as f32,
-                                y: SQUARE_SIZE as f32,
-                            },
-                            if idx == 0 {
-                                Color::DARKBLUE
-                            } else {
-                                Color::BLUE
-                            },
-                        );
-                    }
-
-                    //Fruit
-                    if let Some(pos) = self.fruit_position {
-                        context.draw_rectangle_v(
-                            Vector2 {
-                                x: (pos.0 * SQUARE_SIZE) as f32,
-                                y: (pos.1 * SQUARE_SIZE) as f32,
-                            },
-                            Vector2 {
-                                x: SQUARE_SIZE as f32,
-                                y: SQUARE_SIZE as f32,
-                            },
-                            Color::GREEN,
-                        );
-                    }
-
-                    //Pause screen
-
-                    if window.pause {
-                        context.draw_text(
-                            "GAME PAUSED",
-                            ((self.board_size.0 * SQUARE_SIZE) / 2
-                                - context.measure_text("GAME PAUSED", 40) as isize)
-                                as i32,
-                            ((self.board_size.1 * SQUARE_SIZE) / 2) as i32 - 40,
-                            40,
-                            Color::GRAY,
-                        );
+            Mode::Agent(_) | Mode::External { .. } | Mode::Replay { .. } => {
+                if let Some(window) = &mut self.window {
+                    if !self.game_over {
+                        let frame = next_frame(window.pause, &mut window.frames_counter);
+                        if frame.is_some_and(|frame| frame.is_multiple_of(10))
+                            && self.external_move_ready()
+                        {
+                            self.step_agent();
+                        }
+                    } else {
+                        self.window_game_over_frame();
                     }
-                } else {
-                    let msg = "PRESS [ENTER] TO PLAY AGAIN";
-                    context.draw_text(
-                        msg,
-                        ((self.board_size.0 * SQUARE_SIZE) / 2) as i32
-                            - context.measure_text(msg, 20) / 2,
-                        ((self.board_size.1 * SQUARE_SIZE) / 2 - 40) as i32,
-                        40,
-                        Color::GRAY,
-                    );
                 }
             }
-            _ => return,
+        }
+    }
+    // Game over screen frame with the window's counters and keys
+    fn window_game_over_frame(&mut self) {
+        let Some(window) = &mut self.window else {
+            return;
         };
+        let enter_pressed = window.handle.is_key_pressed(KeyboardKey::KEY_ENTER);
+        let (mut frames, mut in_menu) = (window.game_over_frames, window.in_menu);
+        self.game_over_frame(&mut frames, enter_pressed, &mut in_menu);
+        if let Some(window) = &mut self.window {
+            window.game_over_frames = frames;
+            window.in_menu = in_menu;
+        }
+    }
+    // One frame on the game over screen, starting the next game once the configured
+    // behavior says so
+    fn game_over_frame(
+        &mut self,
+        game_over_frames: &mut usize,
+        enter_pressed: bool,
+        in_menu: &mut bool,
+    ) {
+        if should_restart(
+            &self.config.game_over,
+            game_over_frames,
+            enter_pressed,
+            in_menu,
+        ) {
+            self.reset();
+            *game_over_frames = 0;
+        }
     }
+    // One frame of keyboard play: the key read this frame, if the turn isn't used up yet,
+    // steers the snake, and every 10th frame it moves
+    fn keyboard_frame(
+        &mut self,
+        frame: usize,
+        key: Option<KeyboardKey>,
+        noop_key: KeyboardKey,
+        eat_held: bool,
+        allow_move: &mut bool,
+    ) {
+        if *allow_move {
+            let direction = match key {
+                Some(KeyboardKey::KEY_RIGHT) => Some((1, 0)),
+                Some(KeyboardKey::KEY_LEFT) => Some((-1, 0)),
+                Some(KeyboardKey::KEY_DOWN) => Some((0, 1)),
+                Some(KeyboardKey::KEY_UP) => Some((0, -1)),
+                _ => None,
+            };
+            if let Some(direction) = direction {
+                turn(
+                    &mut self.snake_velocity,
+                    &mut self.turned_this_step,
+                    direction,
+                );
+                *allow_move = false;
+            } else if key == Some(noop_key) {
+                // Choosing to carry on straight still uses up the turn
+                *allow_move = false;
+            }
+        }
 
-    fn run_as_game(&mut self) {
-        loop {
-            if let Some(window) = &mut self.window {
-                if window.handle.window_should_close() {
-                    return;
-                }
+        let last_position = *self.snake_position.last().unwrap();
+        let step = frame.is_multiple_of(10);
+        if step {
+            *allow_move = true;
+        }
+        // Eating is checked every frame, so holding the key over a fruit is enough
+        self.eat_requested = eat_held;
+
+        if step {
+            let before = self.snapshot();
+            self.update_snake();
+            if self.game_over && self.config.coop_takeover {
+                self.take_over(&before);
             }
-            self.update_game();
-            self.draw_game();
         }
+        // Eating is checked every frame here, so the head is always where the fruit is looked for
+        self.update_env(last_position, None);
+    }
+    // Co-op rescue: the fatal step is undone and the built-in agent steers the same snake
+    // until it next eats, then the keyboard gets it back
+    fn take_over(&mut self, before: &Snapshot) {
+        self.restore(before);
+        self.events
+            .retain(|event| !matches!(event, GameEvent::Died));
+        self.control_mode = Mode::Agent(Box::new(FloodFillAgent));
+        self.rescuing = true;
+    }
+    fn hand_back(&mut self) {
+        self.control_mode = Mode::Keyboard;
+        self.rescuing = false;
+    }
+    fn observe(&self) -> Observation {
+        Observation {
+            snake: self.snake_position.clone(),
+            fruit: self.fruit_position,
+            velocity: self.snake_velocity,
+            board_size: self.board_size,
+            wrap: self.config.walls == WallBehavior::Wrap,
+            fog: self.config.fog_radius,
+            mask: self.config.playable_mask.clone(),
+            eat_requires_action: self.config.eat_requires_action,
+        }
+    }
+    // The whole board as the game knows it, with no fog whatever the agent is shown
+    fn observe_board(&self) -> Observation {
+        Observation {
+            fog: None,
+            ..self.observe()
+        }
+    }
+    fn is_playable(&self, cell: (isize, isize)) -> bool {
+        cell_playable(self.board_size, self.config.playable_mask.as_deref(), cell)
+    }
+    fn playable_count(&self) -> isize {
+        match &self.config.playable_mask {
+            Some(mask) => mask.iter().filter(|&&playable| playable).count() as isize,
+            None => self.board_size.0 * self.board_size.1,
+        }
+    }
+    // Free cells reachable from `from`, with the body as walls and wrap mode respected
+    // `from` may be taken by the snake, so the head's own region can be measured
+    fn reachable_free_cells(&self, from: (isize, isize)) -> usize {
+        self.observe_board().reachable_area_around(from)
+    }
+    fn apply_move(&mut self, mv: Move) {
+        let (x, y) = self.snake_velocity;
+        match mv {
+            Move::LT => turn(
+                &mut self.snake_velocity,
+                &mut self.turned_this_step,
+                (y, -x),
+            ),
+            Move::RT => turn(
+                &mut self.snake_velocity,
+                &mut self.turned_this_step,
+                (-y, x),
+            ),
+            // Reversing into the body is refused, same as with the keyboard
+            Move::FW | Move::BW | Move::PS => (),
+            Move::EA => self.eat_requested = true,
+        }
+    }
+    // The window keeps drawing and reading keys while an outside controller thinks, so its
+    // step only runs once the move has arrived, and otherwise waits for a later tick
+    // Headless runs skip this and block in step_agent instead
+    fn external_move_ready(&mut self) -> bool {
+        let Mode::External { moves, .. } = &self.control_mode else {
+            return true;
+        };
+        if self.next_external_move.is_some() {
+            return true;
+        }
+        match moves.try_recv() {
+            Ok(mv) => {
+                self.next_external_move = Some(mv);
+                true
+            }
+            Err(std::sync::mpsc::TryRecvError::Empty) => false,
+            // step_agent finds the channel closed too and ends the game
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => true,
+        }
+    }
+    fn step_agent(&mut self) {
+        // Recorded runs bring their own fruit, so nothing is spawned here
+        if let Mode::Replay { replay, cursor } = &mut self.control_mode {
+            match replay.steps.get(*cursor) {
+                Some(&(fruit, mv)) => {
+                    *cursor += 1;
+                    self.fruit_position = fruit;
+                    self.step(mv);
+                }
+                None => self.game_over = true,
+            }
+            return;
+        }
+
+        if self.fruit_position.is_none() {
+            let last_position = *self.snake_position.last().unwrap();
+            self.spawn_fruit(last_position);
+        }
+
+        if self.recording.as_ref().is_some_and(|r| r.wants_keyframe()) {
+            let snapshot = self.snapshot();
+            if let Some(recording) = &mut self.recording {
+                recording.keyframes.push((recording.steps.len(), snapshot));
+            }
+        }
+
+        let started = std::time::Instant::now();
+        let obs = self.observe();
+        let seen = self.delayed(&obs);
+        self.time_section("observe", started);
+        let mv = if self.repeat_left > 0 {
+            // The heading chosen last time is held, so the agent isn't asked this step
+            self.repeat_left -= 1;
+            Move::FW
+        } else {
+            let started = std::time::Instant::now();
+            let mv = match &mut self.control_mode {
+                Mode::Agent(agent) => agent.decide(&seen),
+                // Blocking on exactly one move per step keeps an outside controller in lockstep
+                // with the game clock, however fast or slow the renderer runs
+                Mode::External { moves, .. } => match self.next_external_move.take() {
+                    Some(mv) => mv,
+                    None => match moves.recv() {
+                        Ok(mv) => mv,
+                        Err(_) => {
+                            self.game_over = true;
+                            return;
+                        }
+                    },
+                },
+                _ => return,
+            };
+            if let Some(latencies) = &mut self.decision_latency {
+                latencies.push(started.elapsed());
+            }
+            self.time_section("decide", started);
+            self.repeat_left = self.config.action_repeat.saturating_sub(1);
+            mv
+        };
+        if let Some(recording) = &mut self.recording {
+            recording.push_step(&obs, mv);
+        }
+        if let Some(log) = &mut self.decision_log {
+            log.push(decision_record(self.steps_taken, &obs, mv));
+        }
+        let score_before = self.score;
+        self.step(mv);
+        if self.rescuing && self.events.iter().any(|e| matches!(e, GameEvent::Ate)) {
+            self.hand_back();
+        }
+        self.repeat_reward += self.score - score_before;
+        if let Some(recording) = &mut self.recording {
+            recording.outcome = Some((self.score, self.snake_position.len()));
+        }
+
+        // External controllers get one reward per move they sent, covering all repeated steps
+        if self.repeat_left == 0 || self.game_over {
+            if let Mode::External { reward, .. } = &self.control_mode {
+                // The controller may have hung up already, which ends the game on the next step
+                let clipped = match self.config.reward_clip {
+                    Some(clip) => self.repeat_reward.clamp(-clip, clip),
+                    None => self.repeat_reward,
+                };
+                let normalized = if self.config.normalize_rewards {
+                    self.reward_stats.push(clipped as f64);
+                    self.reward_stats.normalize(clipped as f64)
+                } else {
+                    clipped as f64
+                };
+                let _ = reward.send((clipped, normalized));
+            }
+            self.repeat_reward = 0;
+        }
+    }
+    // Observation from `observation_delay` steps back, the episode's first one standing in
+    // until that many steps were taken
+    fn delayed(&mut self, obs: &Observation) -> Observation {
+        if self.config.observation_delay == 0 {
+            return obs.clone();
+        }
+        self.observations.push_back(obs.clone());
+        if self.observations.len() > self.config.observation_delay + 1 {
+            self.observations.pop_front();
+        }
+        self.observations
+            .front()
+            .cloned()
+            .unwrap_or_else(|| obs.clone())
+    }
+    fn step(&mut self, mv: Move) {
+        let last_position = *self.snake_position.last().unwrap();
+        self.apply_move(mv);
+        let sitting_on = self
+            .fruit_position
+            .filter(|&fruit| fruit == self.snake_position[0]);
+
+        let started = std::time::Instant::now();
+        self.update_snake();
+        self.time_section("update_snake", started);
+        if !self.game_over {
+            let started = std::time::Instant::now();
+            self.update_env(last_position, sitting_on);
+            self.time_section("update_env", started);
+        }
+        self.eat_requested = false;
+    }
+    fn time_section(&mut self, name: &'static str, started: std::time::Instant) {
+        let Some(sections) = &mut self.profile else {
+            return;
+        };
+        match sections.iter_mut().find(|(section, _)| *section == name) {
+            Some((_, total)) => *total += started.elapsed(),
+            None => sections.push((name, started.elapsed())),
+        }
+    }
+    // Saves the running game once the interval has passed, a failed write only gets reported
+    fn autosave(&mut self) {
+        let Some(window) = &self.window else {
+            return;
+        };
+        let Some(path) = &window.autosave else {
+            return;
+        };
+        let now = window.handle.get_time();
+        let due = autosave_due(
+            now - window.last_autosave,
+            window.autosave_every,
+            window.pause,
+            self.game_over,
+            window.autosaved_game_over,
+        );
+        if !due {
+            return;
+        }
+        if let Err(e) = save_game(path, &self.snapshot()) {
+            eprintln!("Failed to autosave to {}: {}", path, e);
+        }
+        if let Some(window) = &mut self.window {
+            window.last_autosave = now;
+            window.autosaved_game_over = self.game_over;
+        }
+    }
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            snake: self.snake_position.clone(),
+            fruit: self.fruit_position,
+            velocity: self.snake_velocity,
+            pending_growth: self.pending_growth,
+            game_over: self.game_over,
+            rng_state: self.rng.state(),
+            score: self.score,
+            steps_taken: self.steps_taken,
+            lives: self.lives,
+            coverage_awarded: self.coverage_awarded,
+            exploration_score: self.exploration_score,
+            credited_lines: self.credited_lines.clone(),
+            visited: self.visited.clone(),
+            shrink_powerups: self.shrink_powerups.clone(),
+        }
+    }
+    fn restore(&mut self, snapshot: &Snapshot) {
+        self.snake_position = snapshot.snake.clone();
+        self.fruit_position = snapshot.fruit;
+        self.snake_velocity = snapshot.velocity;
+        self.pending_growth = snapshot.pending_growth;
+        self.game_over = snapshot.game_over;
+        if let Some(state) = snapshot.rng_state {
+            self.rng.set_state(state);
+        }
+        self.score = snapshot.score;
+        self.steps_taken = snapshot.steps_taken;
+        self.lives = snapshot.lives;
+        self.coverage_awarded = snapshot.coverage_awarded;
+        self.exploration_score = snapshot.exploration_score;
+        // Older saves carry no grids, the ones from the last reset stand in for them
+        if !snapshot.credited_lines.0.is_empty() {
+            self.credited_lines = snapshot.credited_lines.clone();
+        }
+        if !snapshot.visited.is_empty() {
+            self.visited = snapshot.visited.clone();
+        }
+        self.shrink_powerups = snapshot.shrink_powerups.clone();
+    }
+    // Jumps to the state right before the given step of a recording,
+    // starting from the closest keyframe instead of the beginning
+    fn seek(&mut self, replay: &Replay, step: usize) {
+        let step = step.min(replay.steps.len());
+        let Some((start, snapshot)) = replay.keyframe_before(step) else {
+            return;
+        };
+        self.restore(snapshot);
+        for &(fruit, mv) in &replay.steps[*start..step] {
+            self.fruit_position = fruit;
+            self.step(mv);
+        }
+    }
+    // Re-simulates a recording from its first keyframe with the current rules, checking
+    // every later keyframe and the recorded outcome along the way
+    // The game has to be configured the same way as when the replay was recorded
+    fn validate_replay(&mut self, replay: &Replay) -> Result<(), Divergence> {
+        let Some((start, snapshot)) = replay.keyframes.first() else {
+            return Err(Divergence {
+                step: 0,
+                reason: "replay has no keyframes".to_string(),
+            });
+        };
+        self.reset();
+        self.restore(snapshot);
+        for (i, &(fruit, mv)) in replay.steps.iter().enumerate().skip(*start) {
+            self.fruit_position = fruit;
+            if let Some((_, expected)) = replay.keyframes.iter().find(|(at, _)| *at == i) {
+                // Replayed fruit never comes from the generator, so its state isn't compared
+                let actual = Snapshot {
+                    rng_state: expected.rng_state,
+                    ..self.snapshot()
+                };
+                if actual != *expected {
+                    return Err(Divergence {
+                        step: i,
+                        reason: "state differs from the keyframe".to_string(),
+                    });
+                }
+            }
+            self.step(mv);
+        }
+        if let Some((score, length)) = replay.outcome {
+            let actual = (self.score, self.snake_position.len());
+            if actual != (score, length) {
+                return Err(Divergence {
+                    step: replay.steps.len(),
+                    reason: format!(
+                        "expected score {} and length {}, got {} and {}",
+                        score, length, actual.0, actual.1
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+    // State before every step of a recording and after the last one, for rendering
+    fn replay_states(&mut self, replay: &Replay) -> Vec<Snapshot> {
+        let Some((start, snapshot)) = replay.keyframes.first() else {
+            return Vec::new();
+        };
+        self.reset();
+        self.restore(snapshot);
+        let mut states = Vec::new();
+        for &(fruit, mv) in &replay.steps[*start..] {
+            self.fruit_position = fruit;
+            states.push(self.snapshot());
+            self.step(mv);
+        }
+        states.push(self.snapshot());
+        states
+    }
+    // Re-derives the digest of a recording from its moves, true when it matches the stored one
+    // and the moves lead to the claimed outcome
+    // Like validation, this needs the game configured as when the replay was recorded
+    fn verify_digest(&mut self, replay: &Replay) -> bool {
+        let (Some(expected), Some((start, snapshot))) = (replay.digest, replay.keyframes.first())
+        else {
+            return false;
+        };
+        self.reset();
+        self.restore(snapshot);
+        let mut digest = DIGEST_SEED;
+        for &(fruit, mv) in &replay.steps[*start..] {
+            self.fruit_position = fruit;
+            digest = fold_digest(digest, &self.observe(), mv);
+            self.step(mv);
+        }
+        digest == expected && replay.outcome == Some((self.score, self.snake_position.len()))
+    }
+    // Plays without a window until game over or the step limit, returning the final length
+    fn run_headless(&mut self, max_steps: usize) -> usize {
+        for step in 0..max_steps {
+            if self.game_over {
+                break;
+            }
+            self.step_agent();
+
+            // Closing the preview only stops the drawing, the run carries on
+            if self
+                .window
+                .as_ref()
+                .is_some_and(|window| window.handle.window_should_close())
+            {
+                self.detach_window();
+            }
+            let preview = self
+                .window
+                .as_ref()
+                .is_some_and(|window| should_preview(step, window.preview_every));
+            if preview {
+                self.draw_game();
+            } else {
+                // Nobody draws this step, so its events are dropped
+                self.events.clear();
+            }
+        }
+        self.snake_position.len()
+    }
+    // Runs headless with the given agent or closure picking every move
+    fn run_headless_with(&mut self, policy: impl Agent + 'static, max_steps: usize) -> usize {
+        self.control_mode = Mode::Agent(Box::new(policy));
+        self.run_headless(max_steps)
+    }
+    fn update_snake(&mut self) {
+        self.turned_this_step = false;
+        self.steps_taken += 1;
+        if self.config.walls == WallBehavior::Bounce
+            && !self.is_playable(self.ahead_of(self.snake_position[0]))
+        {
+            // Reflecting off the wall, the tail becomes the head and carries on the way the
+            // body runs into it, since heading straight back hits a bent snake's own body
+            self.snake_velocity = match self.snake_position[..] {
+                [.., before, tail] => (tail.0 - before.0, tail.1 - before.1),
+                _ => (-self.snake_velocity.0, -self.snake_velocity.1),
+            };
+            self.snake_position.reverse();
+        }
+        let saved_position = self.snake_position[0];
+        let saved_tail = *self.snake_position.last().unwrap();
+        let next = if self.in_grace_period() {
+            self.grace_cell(saved_position)
+        } else {
+            self.ahead_of(saved_position)
+        };
+        for i in (1..self.snake_position.len()).rev() {
+            self.snake_position[i] = self.snake_position[i - 1];
+        }
+        self.snake_position[0] = next;
+        let unwrapped = (
+            saved_position.0 + self.snake_velocity.0,
+            saved_position.1 + self.snake_velocity.1,
+        );
+        if self.snake_position[0] != unwrapped {
+            self.events.push(GameEvent::Wrapped(self.snake_position[0]));
+        }
+
+        // Tail stays in place for one step per queued segment
+        if self.pending_growth > 0 {
+            self.snake_position.push(saved_tail);
+            self.pending_growth -= 1;
+        } else {
+            self.events.push(GameEvent::Vacated(saved_tail));
+        }
+
+        // Aging: the oldest segment drops off on a fixed beat, eaten or not
+        let decays = self
+            .config
+            .tail_decay_interval
+            .is_some_and(|every| every > 0 && self.steps_taken.is_multiple_of(every));
+        if decays && self.snake_position.len() > 1 {
+            let tail = self.snake_position.pop().unwrap();
+            self.events.push(GameEvent::Vacated(tail));
+        }
+
+        self.check_collisions();
+        self.visit_head();
+        // Without fruit the only thing left to reward is staying alive
+        if self.config.disable_fruit && !self.game_over {
+            self.score += 1;
+        }
+        if cfg!(debug_assertions) {
+            self.check_invariants();
+        }
+    }
+    // The cells the snake starts on count as visited, without paying any bonus for them,
+    // so their share of the coverage bonus is marked as already awarded
+    fn visit_start(&mut self) {
+        for i in 0..self.snake_position.len() {
+            let (x, y) = self.snake_position[i];
+            if self.is_playable((x, y)) {
+                self.visited[y as usize][x as usize] = true;
+            }
+        }
+        self.coverage_awarded = self.coverage_earned();
+    }
+    fn visit_head(&mut self) {
+        let head = self.snake_position[0];
+        if head.0 < 0 || head.1 < 0 || head.0 >= self.board_size.0 || head.1 >= self.board_size.1 {
+            return;
+        }
+        let first_visit = !self.visited[head.1 as usize][head.0 as usize];
+        self.visited[head.1 as usize][head.0 as usize] = true;
+
+        if first_visit && self.config.exploration_bonus != 0 {
+            let bonus = self.exploration_bonus_now();
+            self.score += bonus;
+            self.exploration_score += bonus;
+        }
+
+        if self.config.coverage_bonus != 0 {
+            let earned = self.coverage_earned();
+            self.score += earned - self.coverage_awarded;
+            self.coverage_awarded = earned;
+        }
+    }
+    // Bonus is paid out as coverage grows, reaching the full amount once every cell was visited
+    fn coverage_earned(&self) -> isize {
+        let visited = self.visited.iter().flatten().filter(|&&v| v).count() as isize;
+        self.config.coverage_bonus * visited / self.playable_count().max(1)
+    }
+    // What a never visited cell is worth at the current step
+    fn exploration_bonus_now(&self) -> isize {
+        let half_life = self.config.exploration_half_life;
+        if half_life == 0 {
+            return self.config.exploration_bonus;
+        }
+        let decay = 0.5f64.powf(self.steps_taken as f64 / half_life as f64);
+        (self.config.exploration_bonus as f64 * decay).round() as isize
+    }
+    // Share of the board the head has visited this game, in percent
+    fn coverage(&self) -> f64 {
+        let visited = self.visited.iter().flatten().filter(|&&v| v).count();
+        100.0 * visited as f64 / self.playable_count() as f64
+    }
+    fn in_grace_period(&self) -> bool {
+        self.steps_taken <= self.config.spawn_grace_steps
+    }
+    // Where the head goes during the grace period: straight on if that's safe, else the cell
+    // wrapped back onto the board, else the first safe turn, so the snake is never left
+    // overlapping itself or inside a wall once grace ends. Only a boxed-in snake still dies
+    fn grace_cell(&mut self, head: (isize, isize)) -> (isize, isize) {
+        // The tail moves out of the way unless it's held back to grow
+        let staying = self.snake_position.len() - (self.pending_growth == 0) as usize;
+        let body = &self.snake_position[..staying];
+        let safe = |cell| self.is_playable(cell) && !body.contains(&cell);
+        let ahead = self.ahead_of(head);
+        let wrapped = (
+            ahead.0.rem_euclid(self.board_size.0),
+            ahead.1.rem_euclid(self.board_size.1),
+        );
+        if safe(ahead) {
+            return ahead;
+        }
+        if safe(wrapped) {
+            return wrapped;
+        }
+        // Never back over the neck, even when it's the tail about to move away
+        let neck = self.snake_position.get(1).copied();
+        let turn = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+            .into_iter()
+            .map(|(dx, dy)| ((dx, dy), (head.0 + dx, head.1 + dy)))
+            .find(|&(_, cell)| safe(cell) && Some(cell) != neck);
+        match turn {
+            Some((velocity, cell)) => {
+                self.snake_velocity = velocity;
+                cell
+            }
+            None => ahead,
+        }
+    }
+    // Debug aid: a live snake must be on the board and never overlap itself
+    // Every segment is checked, not just the head, since self-collisions compare cells
+    // directly and only work while wrap mode keeps all of them in canonical board range
+    fn check_invariants(&self) {
+        if self.game_over {
+            return;
+        }
+        let on_board = self
+            .snake_position
+            .iter()
+            .all(|&cell| self.is_playable(cell));
+        let overlapping = (1..self.snake_position.len())
+            .any(|i| self.snake_position[..i].contains(&self.snake_position[i]));
+        if !on_board || overlapping {
+            panic!(
+                "Snake invariant broken (on board: {}, overlapping: {}): snake {:?}, velocity {:?}, fruit {:?}, board {:?}",
+                on_board,
+                overlapping,
+                self.snake_position,
+                self.snake_velocity,
+                self.fruit_position,
+                self.board_size
+            );
+        }
+    }
+    // Cell one step along the current velocity, wrapped around the board in wrap mode
+    // Body segments only ever copy already wrapped cells, so the snake stays contiguous
+    fn ahead_of(&self, cell: (isize, isize)) -> (isize, isize) {
+        let next = (
+            cell.0 + self.snake_velocity.0,
+            cell.1 + self.snake_velocity.1,
+        );
+        if self.config.walls == WallBehavior::Wrap {
+            (
+                next.0.rem_euclid(self.board_size.0),
+                next.1.rem_euclid(self.board_size.1),
+            )
+        } else {
+            next
+        }
+    }
+    fn check_collisions(&mut self) {
+        if !self.is_playable(self.snake_position[0]) {
+            self.game_over = true;
+        }
+
+        for i in 1..self.snake_position.len() {
+            if self.snake_position[0] == self.snake_position[i] {
+                self.game_over = true;
+            }
+        }
+
+        if self.game_over && self.lives > 0 {
+            if self.config.practice.is_some() {
+                if let Some(checkpoint) = self.checkpoint.clone() {
+                    // Lives are the one thing the rewind doesn't give back
+                    let lives = self.lives;
+                    self.restore(&checkpoint);
+                    self.lives = lives - 1;
+                }
+            } else if let Some(cell) = self.respawn_cell() {
+                // Arcade lives start over from a single segment but keep the score
+                self.snake_position = vec![cell];
+                self.pending_growth = 0;
+                self.game_over = false;
+                self.lives -= 1;
+            }
+        }
+        if self.game_over {
+            self.events.push(GameEvent::Died);
+        }
+    }
+    // Free cell closest to the middle of the board, preferring the most open one on ties
+    fn respawn_cell(&self) -> Option<(isize, isize)> {
+        let (w, h) = self.board_size;
+        self.free_cells().into_iter().max_by_key(|&(x, y)| {
+            let from_center = (2 * x - (w - 1)).abs().max((2 * y - (h - 1)).abs());
+            (-from_center, self.reachable_free_cells((x, y)))
+        })
+    }
+    // `sitting_on` is a fruit the head was over before this step's move, which agents can
+    // still eat on the way off it, the way a keyboard player can between steps
+    fn update_env(&mut self, last_position: (isize, isize), sitting_on: Option<(isize, isize)>) {
+        if self.fruit_position.is_none() {
+            self.spawn_fruit(last_position);
+        };
+
+        if let Some(pos) = self.fruit_position {
+            // In the puzzle variant the snake passes over the fruit unless told to eat it
+            let eats = !self.config.eat_requires_action || self.eat_requested;
+            let grows_at_head =
+                !self.config.delayed_growth && self.config.growth_side == GrowthSide::Head;
+            if (self.snake_position[0] == pos || sitting_on == Some(pos)) && eats {
+                // Growing the head into a wall or the body is checked before the meal is
+                // credited, so neither a death nor a rewind leaves it counted
+                let died = grows_at_head && {
+                    let head = self.ahead_of(self.snake_position[0]);
+                    self.snake_position.insert(0, head);
+                    let lives = self.lives;
+                    self.check_collisions();
+                    self.game_over || self.lives < lives
+                };
+                if !died {
+                    self.score += FRUIT_REWARD;
+                    self.fruits_eaten += 1;
+                    self.events.push(GameEvent::Ate);
+                    if self.config.delayed_growth {
+                        self.pending_growth += 1;
+                    } else if !grows_at_head {
+                        self.snake_position.push(last_position);
+                    }
+                    self.fruit_position = None;
+
+                    if self.config.practice.is_some() {
+                        self.checkpoint = Some(self.snapshot());
+                    }
+                }
+            }
+        }
+
+        if self.config.relocate_unreachable_fruit && !self.game_over {
+            self.relocate_unreachable_fruit();
+        }
+
+        if self.config.shrink_amount > 0 {
+            self.update_shrink_powerups();
+        }
+
+        if self.config.line_bonus != 0 {
+            self.credit_full_lines();
+        }
+
+        // Strong agents on small boards could otherwise keep going forever
+        let capped = self.config.score_cap.is_some_and(|cap| self.score >= cap)
+            || self
+                .config
+                .length_cap
+                .is_some_and(|cap| self.snake_position.len() >= cap);
+        if capped && !self.game_over {
+            self.game_over = true;
+            self.solved = true;
+        }
+    }
+    // Keeps one shrink power-up on the board and applies it when the head reaches it
+    fn update_shrink_powerups(&mut self) {
+        let head = self.snake_position[0];
+        if let Some(idx) = self.shrink_powerups.iter().position(|&pos| pos == head) {
+            self.shrink_powerups.remove(idx);
+            // Never below the head, and any pending growth is cancelled along with the tail
+            let length = self
+                .snake_position
+                .len()
+                .saturating_sub(self.config.shrink_amount)
+                .max(1);
+            self.snake_position.truncate(length);
+            self.pending_growth = 0;
+        }
+
+        if self.shrink_powerups.is_empty() {
+            let cells = self.free_cells();
+            if let Some(&cell) = cells.choose(&mut self.rng) {
+                self.shrink_powerups.push(cell);
+            }
+        }
+    }
+    fn credit_full_lines(&mut self) {
+        let mut row_counts = vec![0; self.board_size.1 as usize];
+        let mut column_counts = vec![0; self.board_size.0 as usize];
+        for &(x, y) in &self.snake_position {
+            if x >= 0 && y >= 0 && x < self.board_size.0 && y < self.board_size.1 {
+                row_counts[y as usize] += 1;
+                column_counts[x as usize] += 1;
+            }
+        }
+        // Cells cut out by a mask count as filled, but only for lines the snake is in
+        for y in 0..self.board_size.1 {
+            for x in 0..self.board_size.0 {
+                if !self.is_playable((x, y)) {
+                    if row_counts[y as usize] > 0 {
+                        row_counts[y as usize] += 1;
+                    }
+                    if column_counts[x as usize] > 0 {
+                        column_counts[x as usize] += 1;
+                    }
+                }
+            }
+        }
+
+        let (rows, columns) = &mut self.credited_lines;
+        for (count, credited) in row_counts.iter().zip(rows.iter_mut()) {
+            if *count == self.board_size.0 && !*credited {
+                *credited = true;
+                self.score += self.config.line_bonus;
+            }
+        }
+        for (count, credited) in column_counts.iter().zip(columns.iter_mut()) {
+            if *count == self.board_size.1 && !*credited {
+                *credited = true;
+                self.score += self.config.line_bonus;
+            }
+        }
+    }
+    fn spawn_fruit(&mut self, fallback: (isize, isize)) {
+        if self.config.disable_fruit {
+            return;
+        }
+        if self.config.spawn_bias == SpawnBias::Uniform && !self.config.spawn_protection {
+            let cells = self.free_cells();
+            self.fruit_position = Some(cells.choose(&mut self.rng).copied().unwrap_or(fallback));
+        } else {
+            let mut cells = self.free_cells();
+            if self.config.spawn_protection {
+                // Tiny boards may have no other free cell, in which case the protection is dropped
+                let next_head = self.ahead_of(self.snake_position[0]);
+                if cells.iter().any(|&cell| cell != next_head) {
+                    cells.retain(|&cell| cell != next_head);
+                }
+            }
+            let weighted: Vec<_> = cells
+                .into_iter()
+                .map(|cell| (cell, self.spawn_weight(cell)))
+                .collect();
+            self.fruit_position = weighted
+                .choose_weighted(&mut self.rng, |&(_, weight)| weight)
+                .ok()
+                .map(|&(cell, _)| cell)
+                .or(Some(fallback));
+        }
+
+        self.fruits_spawned += self.fruit_position.is_some() as usize;
+        if let (Some(log), Some(pos)) = (&mut self.fruit_log, self.fruit_position) {
+            log.push(pos);
+        }
+    }
+    // Moves a fruit the head can no longer get to onto a random cell it can
+    fn relocate_unreachable_fruit(&mut self) {
+        let Some(fruit) = self.fruit_position else {
+            return;
+        };
+        let reachable = self.observe_board().reachable_from_head();
+        if reachable.contains(&fruit) {
+            return;
+        }
+        // The tail counts as reachable since it moves away, but the fruit can't go there
+        let free: Vec<_> = reachable
+            .into_iter()
+            .filter(|cell| {
+                !self.snake_position.contains(cell) && !self.shrink_powerups.contains(cell)
+            })
+            .collect();
+        if let Some(&cell) = free.choose(&mut self.rng) {
+            self.fruit_position = Some(cell);
+            self.fruits_spawned += 1;
+            if let Some(log) = &mut self.fruit_log {
+                log.push(cell);
+            }
+        }
+    }
+    fn free_cells(&self) -> Vec<(isize, isize)> {
+        (0..self.board_size.1)
+            .flat_map(|y| (0..self.board_size.0).map(move |x| (x, y)))
+            .filter(|cell| {
+                self.is_playable(*cell)
+                    && !self.snake_position.contains(cell)
+                    && !self.shrink_powerups.contains(cell)
+                    && self.fruit_position != Some(*cell)
+            })
+            .collect()
+    }
+    fn spawn_weight(&self, cell: (isize, isize)) -> f64 {
+        // Ring distance from the middle of the board, 0 at the center
+        let center = (
+            (self.board_size.0 - 1) as f64 / 2.0,
+            (self.board_size.1 - 1) as f64 / 2.0,
+        );
+        let distance = (cell.0 as f64 - center.0)
+            .abs()
+            .max((cell.1 as f64 - center.1).abs());
+        let max_distance = center.0.max(center.1);
+
+        match self.config.spawn_bias {
+            SpawnBias::Uniform => 1.0,
+            SpawnBias::Center => max_distance - distance + 1.0,
+            SpawnBias::Edge => distance + 1.0,
+        }
+    }
+    // Mean and 95th percentile of the recorded decision latencies
+    fn latency_stats(&self) -> Option<(std::time::Duration, std::time::Duration)> {
+        let mut latencies = self.decision_latency.clone()?;
+        if latencies.is_empty() {
+            return None;
+        }
+        latencies.sort();
+        let mean = latencies.iter().sum::<std::time::Duration>() / latencies.len() as u32;
+        let p95 = latencies[(latencies.len() * 95).div_ceil(100) - 1];
+        Some((mean, p95))
+    }
+    // Share of the fruit spawned this game that got eaten, None before any spawned
+    fn fruit_capture_rate(&self) -> Option<f64> {
+        (self.fruits_spawned > 0).then(|| self.fruits_eaten as f64 / self.fruits_spawned as f64)
+    }
+    // Score for filling the whole board with fruit alone, line and coverage bonuses aside,
+    // so fitness can be compared across board sizes
+    fn max_possible_score(&self) -> isize {
+        let start = initial_snake(
+            self.config.start_length,
+            self.board_size,
+            self.config.playable_mask.as_deref(),
+            self.config.start_cell,
+        )
+        .len() as isize;
+        (self.playable_count() - start) * FRUIT_REWARD
+    }
+    // Share of the profiled time each step section took, in percent
+    fn profile_shares(&self) -> Option<Vec<(&'static str, f64)>> {
+        let sections = self.profile.as_ref()?;
+        let total = sections
+            .iter()
+            .map(|(_, time)| *time)
+            .sum::<std::time::Duration>()
+            .as_secs_f64();
+        if total == 0.0 {
+            return None;
+        }
+        Some(
+            sections
+                .iter()
+                .map(|&(name, time)| (name, 100.0 * time.as_secs_f64() / total))
+                .collect(),
+        )
+    }
+    // Every non-empty cell with what's in it, in drawing order: power-ups and the fruit
+    // first, then the snake from tail to head, so the head is always on top
+    // Far smaller than the full grid for mostly empty boards
+    fn occupied_cells(&self) -> Vec<(isize, isize, CellKind)> {
+        let snake = self
+            .snake_position
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(idx, &(x, y))| {
+                let kind = if idx == 0 {
+                    CellKind::Head
+                } else {
+                    CellKind::Body
+                };
+                (x, y, kind)
+            });
+        let powerups = self
+            .shrink_powerups
+            .iter()
+            .map(|&(x, y)| (x, y, CellKind::ShrinkPowerup));
+        let fruit = self.fruit_position.map(|(x, y)| (x, y, CellKind::Fruit));
+        powerups.chain(fruit).chain(snake).collect()
+    }
+    // Board as text framed by walls, '@' for the head, 'o' for the body, '*' for the fruit
+    // and '-' for shrink power-ups, meant for printing and comparing boards
+    fn to_debug_string(&self) -> String {
+        let (w, h) = self.board_size;
+        let mut rows: Vec<Vec<char>> = (0..h)
+            .map(|y| {
+                (0..w)
+                    .map(|x| if self.is_playable((x, y)) { '.' } else { '#' })
+                    .collect()
+            })
+            .collect();
+        for (x, y, kind) in self.occupied_cells() {
+            if x < 0 || y < 0 || x >= w || y >= h {
+                continue;
+            }
+            rows[y as usize][x as usize] = match kind {
+                CellKind::Head => '@',
+                CellKind::Body => 'o',
+                CellKind::Fruit => '*',
+                CellKind::ShrinkPowerup => '-',
+            };
+        }
+        let wall = "#".repeat(w as usize + 2);
+        let mut out = wall.clone() + "\n";
+        for row in rows {
+            out += &format!("#{}#\n", row.into_iter().collect::<String>());
+        }
+        out + &wall + "\n"
+    }
+    // Frequency table of logged fruit spawns, indexed as [y][x]
+    fn fruit_spawn_frequencies(&self) -> Vec<Vec<usize>> {
+        let mut table = vec![vec![0; self.board_size.0 as usize]; self.board_size.1 as usize];
+        for &(x, y) in self.fruit_log.iter().flatten() {
+            table[y as usize][x as usize] += 1;
+        }
+        table
+    }
+    fn draw_game(&mut self) {
+        if let Some(window) = &mut self.window {
+            for event in self.events.drain(..) {
+                match event {
+                    GameEvent::Ate => window.score_pulse = PULSE_FRAMES,
+                    GameEvent::Vacated(cell) if window.trail_frames > 0 => {
+                        window.trail.push((cell, window.trail_frames))
+                    }
+                    GameEvent::Wrapped(cell) if window.show_seams => {
+                        window.wrap_flash = Some((cell, WRAP_FLASH_FRAMES))
+                    }
+                    _ => (),
+                }
+            }
+            window.score_pulse = window.score_pulse.saturating_sub(1);
+            for (_, frames_left) in &mut window.trail {
+                *frames_left -= 1;
+            }
+            window.trail.retain(|&(_, frames_left)| frames_left > 0);
+            window.wrap_flash = window
+                .wrap_flash
+                .filter(|&(_, frames_left)| frames_left > 1)
+                .map(|(cell, frames_left)| (cell, frames_left - 1));
+        }
+        let coverage = self.coverage();
+        let max_score = self.max_possible_score();
+        let cells = self.occupied_cells();
+        let blocked: Vec<_> = (0..self.board_size.1)
+            .flat_map(|y| (0..self.board_size.0).map(move |x| (x, y)))
+            .filter(|&cell| !self.is_playable(cell))
+            .collect();
+        // Largest region the snake can still move into and the free run around the head,
+        // shown with the agent debug overlay to spot traps before they close
+        let sensors = match &self.window {
+            Some(window) if window.show_path && !self.game_over => {
+                let reachable = self.reachable_free_cells(self.snake_position[0]);
+                Some((reachable, self.observe().wall_distances()))
+            }
+            _ => None,
+        };
+        match &mut self.window {
+            Some(window) => {
+                if window.fit_to_window {
+                    let screen = (
+                        window.handle.get_screen_width(),
+                        window.handle.get_screen_height(),
+                    );
+                    (window.offset, window.zoom) = fit_board(screen, self.board_size);
+                }
+                let camera = Camera2D {
+                    offset: window.offset,
+                    target: Vector2 { x: 0.0, y: 0.0 },
+                    rotation: 0.0,
+                    zoom: window.zoom,
+                };
+                let mut frame = window.handle.begin_drawing(&window.thread);
+                frame.clear_background(window.theme.background);
+                // Everything below is laid out in board pixels, the camera places it on screen
+                let mut context = frame.begin_mode2D(camera);
+
+                if !self.game_over {
+                    //Grid lines
+                    for i in 0..=self.board_size.0 {
+                        context.draw_line_v(
+                            Vector2 {
+                                x: (SQUARE_SIZE * i) as f32,
+                                y: 0.0,
+                            },
+                            Vector2 {
+                                x: (SQUARE_SIZE * i) as f32,
+                                y: (self.board_size.1 * SQUARE_SIZE) as f32,
+                            },
+                            window.theme.grid,
+                        )
+                    }
+
+                    for i in 0..=self.board_size.1 {
+                        context.draw_line_v(
+                            Vector2 {
+                                x: 0.0,
+                                y: (SQUARE_SIZE * i) as f32,
+                            },
+                            Vector2 {
+                                x: (self.board_size.0 * SQUARE_SIZE) as f32,
+                                y: (SQUARE_SIZE * i) as f32,
+                            },
+                            window.theme.grid,
+                        )
+                    }
+
+                    //Cells outside a custom board shape, hiding the grid there
+                    for &(x, y) in &blocked {
+                        context.draw_rectangle_v(
+                            Vector2 {
+                                x: (x * SQUARE_SIZE) as f32,
+                                y: (y * SQUARE_SIZE) as f32,
+                            },
+                            Vector2 {
+                                x: SQUARE_SIZE as f32 + 1.0,
+                                y: SQUARE_SIZE as f32 + 1.0,
+                            },
+                            window.theme.background,
+                        );
+                    }
+
+                    //Seams on the edges the snake wraps across, and the cell it last came in on
+                    if window.show_seams && self.config.walls == WallBehavior::Wrap {
+                        let (width, height) = (
+                            (self.board_size.0 * SQUARE_SIZE) as f32,
+                            (self.board_size.1 * SQUARE_SIZE) as f32,
+                        );
+                        let seam = window.theme.head.fade(0.3);
+                        for (corner, size) in [
+                            ((0.0, 0.0), (width, SEAM_WIDTH)),
+                            ((0.0, height - SEAM_WIDTH), (width, SEAM_WIDTH)),
+                            ((0.0, 0.0), (SEAM_WIDTH, height)),
+                            ((width - SEAM_WIDTH, 0.0), (SEAM_WIDTH, height)),
+                        ] {
+                            context.draw_rectangle_v(
+                                Vector2 {
+                                    x: corner.0,
+                                    y: corner.1,
+                                },
+                                Vector2 {
+                                    x: size.0,
+                                    y: size.1,
+                                },
+                                seam,
+                            );
+                        }
+                        if let Some((cell, frames_left)) = window.wrap_flash {
+                            context.draw_rectangle_v(
+                                Vector2 {
+                                    x: (cell.0 * SQUARE_SIZE) as f32,
+                                    y: (cell.1 * SQUARE_SIZE) as f32,
+                                },
+                                Vector2 {
+                                    x: SQUARE_SIZE as f32,
+                                    y: SQUARE_SIZE as f32,
+                                },
+                                window
+                                    .theme
+                                    .head
+                                    .fade(0.6 * fade_alpha(frames_left, WRAP_FLASH_FRAMES)),
+                            );
+                        }
+                    }
+
+                    //Trail of vacated cells
+                    for &(cell, frames_left) in &window.trail {
+                        context.draw_rectangle_v(
+                            Vector2 {
+                                x: (cell.0 * SQUARE_SIZE) as f32,
+                                y: (cell.1 * SQUARE_SIZE) as f32,
+                            },
+                            Vector2 {
+                                x: SQUARE_SIZE as f32,
+                                y: SQUARE_SIZE as f32,
+                            },
+                            window
+                                .theme
+                                .body
+                                .fade(0.5 * fade_alpha(frames_left, window.trail_frames)),
+                        );
+                    }
+
+                    //Snake, power-ups and fruit
+                    draw_cells(&mut context, &cells, &window.theme);
+
+                    //Segment indices
+                    if window.show_indices {
+                        for (idx, snake_segment) in self.snake_position.iter().enumerate() {
+                            let label = idx.to_string();
+                            let (x, y) = centered_text(
+                                (snake_segment.0 * SQUARE_SIZE) as i32,
+                                (snake_segment.1 * SQUARE_SIZE) as i32,
+                                SQUARE_SIZE as i32,
+                                context.measure_text(&label, INDEX_FONT_SIZE),
+                                INDEX_FONT_SIZE,
+                            );
+                            context.draw_text(&label, x, y, INDEX_FONT_SIZE, Color::WHITE);
+                        }
+                    }
+
+                    //Board coordinates
+                    if window.show_axes {
+                        let columns = (0..self.board_size.0).map(|i| (i, true));
+                        let rows = (0..self.board_size.1).map(|i| (i, false));
+                        for (index, column) in columns.chain(rows) {
+                            let (x, y) = axis_label_position(index, column);
+                            context.draw_text(
+                                &index.to_string(),
+                                x,
+                                y,
+                                INDEX_FONT_SIZE,
+                                window.theme.text.fade(0.6),
+                            );
+                        }
+                    }
+
+                    //Planned path
+                    if window.show_path {
+                        if let Mode::Agent(agent) = &self.control_mode {
+                            for cell in agent.planned_path().unwrap_or_default() {
+                                context.draw_rectangle_v(
+                                    Vector2 {
+                                        x: (cell.0 * SQUARE_SIZE) as f32,
+                                        y: (cell.1 * SQUARE_SIZE) as f32,
+                                    },
+                                    Vector2 {
+                                        x: SQUARE_SIZE as f32,
+                                        y: SQUARE_SIZE as f32,
+                                    },
+                                    Color::ORANGE.fade(0.3),
+                                );
+                            }
+                        }
+                    }
+
+                    //HUD
+                    let mut hud = if window.show_max_score {
+                        format!("SCORE: {} / {}", self.score, max_score)
+                    } else {
+                        format!("SCORE: {}", self.score)
+                    };
+                    if window.daily {
+                        hud = format!("DAILY CHALLENGE  {}", hud);
+                    }
+                    if self.config.coverage_bonus != 0 {
+                        hud += &format!("  COVERAGE: {:.0}%", coverage);
+                    }
+                    if self.config.exploration_bonus != 0 {
+                        hud += &format!("  EXPLORED: {}", self.exploration_score);
+                    }
+                    if let Some((reachable, [ahead, _, left, right])) = sensors {
+                        hud += &format!(
+                            "  SPACE: {}  WALLS: {:.0}/{:.0}/{:.0}%",
+                            reachable,
+                            100.0 * left,
+                            100.0 * ahead,
+                            100.0 * right
+                        );
+                    }
+                    context.draw_text(
+                        &hud,
+                        10,
+                        (self.board_size.1 * SQUARE_SIZE) as i32 + 10,
+                        pulse_font_size(20, window.score_pulse),
+                        window.theme.text,
+                    );
+
+                    //Pause screen
+
+                    if window.pause {
+                        let msg = "GAME PAUSED";
+                        let (x, y) = center_text(
+                            (self.board_size.0 * SQUARE_SIZE) as i32,
+                            (self.board_size.1 * SQUARE_SIZE) as i32,
+                            context.measure_text(msg, 40),
+                            40,
+                        );
+                        context.draw_text(msg, x, y, 40, window.theme.text);
+                        if window.resume_offer.is_some() {
+                            let msg = "[L] RESUME SAVED GAME  [SPACE] NEW GAME";
+                            let (x, _) = center_text(
+                                (self.board_size.0 * SQUARE_SIZE) as i32,
+                                (self.board_size.1 * SQUARE_SIZE) as i32,
+                                context.measure_text(msg, 20),
+                                20,
+                            );
+                            context.draw_text(msg, x, y + 50, 20, window.theme.text);
+                        }
+                    }
+                } else if window.in_menu {
+                    let title = "SNAKE";
+                    context.draw_text(
+                        title,
+                        ((self.board_size.0 * SQUARE_SIZE) / 2) as i32
+                            - context.measure_text(title, 40) / 2,
+                        ((self.board_size.1 * SQUARE_SIZE) / 2 - 80) as i32,
+                        40,
+                        window.theme.text,
+                    );
+                    let msg = "PRESS [ENTER] TO START";
+                    context.draw_text(
+                        msg,
+                        ((self.board_size.0 * SQUARE_SIZE) / 2) as i32
+                            - context.measure_text(msg, 20) / 2,
+                        ((self.board_size.1 * SQUARE_SIZE) / 2 - 20) as i32,
+                        20,
+                        window.theme.text,
+                    );
+                } else {
+                    let msg = "PRESS [ENTER] TO PLAY AGAIN";
+                    context.draw_text(
+                        msg,
+                        ((self.board_size.0 * SQUARE_SIZE) / 2) as i32
+                            - context.measure_text(msg, 20) / 2,
+                        ((self.board_size.1 * SQUARE_SIZE) / 2 - 40) as i32,
+                        40,
+                        window.theme.text,
+                    );
+                }
+            }
+            _ => return,
+        };
+    }
+
+    // Starts drawing a game that may already be in progress, e.g. one started headless
+    fn attach_window(&mut self, handle: &'a mut RaylibHandle, thread: &'a mut RaylibThread) {
+        self.window = Some(WindowData::new(handle, thread));
+    }
+    // Drops the window while leaving the game itself untouched
+    fn detach_window(&mut self) {
+        self.window = None;
+    }
+    fn run_as_game(&mut self) {
+        loop {
+            if let Some(window) = &mut self.window {
+                if window.handle.window_should_close() {
+                    return;
+                }
+            }
+            self.update_game();
+            self.draw_game();
+        }
+    }
+}
+
+// Command line options, parsed by hand to keep the dependency list short
+#[derive(Default)]
+struct Options {
+    fruit_log: Option<String>,
+    agent: Option<String>,
+    headless: Option<usize>,
+    attach_after: Option<usize>,
+    record: Option<String>,
+    keyframe_interval: usize,
+    replay: Option<String>,
+    validate_replay: Option<String>,
+    verify_digest: Option<String>,
+    seek: usize,
+    show_indices: bool,
+    show_axes: bool,
+    show_path: bool,
+    trail_frames: usize,
+    show_max_score: bool,
+    show_seams: bool,
+    daily: bool,
+    theme: Theme,
+    tail_color: Option<Color>,
+    vsync: bool,
+    msaa: bool,
+    record_input: Option<String>,
+    play_input: Option<String>,
+    latency_stats: bool,
+    profile: bool,
+    print_board: bool,
+    capture_rate: bool,
+    start_paused: bool,
+    fit_window: bool,
+    preview_every: Option<usize>,
+    noop_key: Option<KeyboardKey>,
+    decision_log: Option<String>,
+    decision_cache: Option<usize>,
+    render_frames: Option<String>,
+    render_fps: Option<usize>,
+    autosave: Option<String>,
+    autosave_every: Option<f64>,
+    rng_name: Option<String>,
+    seed: Option<u64>,
+    config: GameConfig,
+}
+
+impl Options {
+    fn from_args() -> Self {
+        let mut options = Options::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--fruit-log" => options.fruit_log = args.next(),
+                "--board" => {
+                    options.config.board_size = args.next().and_then(|size| {
+                        let (w, h) = size.split_once('x')?;
+                        let (w, h): (isize, isize) = (w.parse().ok()?, h.parse().ok()?);
+                        (w > 0 && h > 0).then_some((w, h))
+                    })
+                }
+                "--mask" => match args.next().as_deref().map(load_mask) {
+                    // The mask brings its own size, replacing any --board
+                    Some(Ok((size, mask))) => {
+                        options.config.board_size = Some(size);
+                        options.config.playable_mask = Some(mask);
+                    }
+                    Some(Err(e)) => eprintln!("Failed to load board mask: {}", e),
+                    None => eprintln!("--mask needs a file"),
+                },
+                "--level" => match args.next().as_deref().map(load_level_image) {
+                    Some(Ok(level)) => {
+                        options.config.board_size = Some(level.board_size);
+                        options.config.playable_mask = Some(level.mask);
+                        options.config.start_cell = Some(level.start);
+                        options.config.start_fruit = level.fruit;
+                    }
+                    Some(Err(e)) => eprintln!("Failed to load level image: {}", e),
+                    None => eprintln!("--level needs an image file"),
+                },
+                "--demo" => match args.next().as_deref().and_then(demo::find) {
+                    // Flags after --demo still override what it sets
+                    Some(demo) => {
+                        options.seed = Some(demo.seed);
+                        options.agent = Some(demo.agent.to_string());
+                        options.config.board_size = Some(demo.board_size);
+                        options.config.start_length = demo.start_length;
+                    }
+                    None => eprintln!(
+                        "Unknown demo, available: {}",
+                        demo::DEMOS
+                            .iter()
+                            .map(|demo| demo.name)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                },
+                "--agent" => options.agent = args.next(),
+                "--record" => options.record = args.next(),
+                "--keyframe-interval" => {
+                    options.keyframe_interval =
+                        args.next().and_then(|n| n.parse().ok()).unwrap_or(0)
+                }
+                "--replay" => options.replay = args.next(),
+                "--validate-replay" => options.validate_replay = args.next(),
+                "--verify-digest" => options.verify_digest = args.next(),
+                "--render-frames" => options.render_frames = args.next(),
+                "--render-fps" => options.render_fps = args.next().and_then(|n| n.parse().ok()),
+                "--seek" => options.seek = args.next().and_then(|n| n.parse().ok()).unwrap_or(0),
+                "--attach-after" => options.attach_after = args.next().and_then(|n| n.parse().ok()),
+                "--headless" => options.headless = args.next().and_then(|n| n.parse().ok()),
+                "--show-indices" => options.show_indices = true,
+                "--show-axes" => options.show_axes = true,
+                "--record-input" => options.record_input = args.next(),
+                "--play-input" => options.play_input = args.next(),
+                "--vsync" => options.vsync = true,
+                "--msaa" => options.msaa = true,
+                "--show-path" => options.show_path = true,
+                "--show-max-score" => options.show_max_score = true,
+                "--daily" => options.daily = true,
+                "--show-seams" => options.show_seams = true,
+                "--trail-fade" => {
+                    options.trail_frames = args.next().and_then(|n| n.parse().ok()).unwrap_or(0)
+                }
+                "--theme" => match args.next().as_deref().and_then(Theme::from_name) {
+                    Some(theme) => options.theme = theme,
+                    None => eprintln!("Unknown theme, keeping the default"),
+                },
+                "--tail-color" => {
+                    options.tail_color = args.next().as_deref().and_then(theme::parse_hex);
+                    if options.tail_color.is_none() {
+                        eprintln!("Tail color should look like ff8800, keeping a solid body");
+                    }
+                }
+                "--latency-stats" => options.latency_stats = true,
+                "--decision-log" => options.decision_log = args.next(),
+                "--autosave" => options.autosave = args.next(),
+                "--autosave-every" => {
+                    options.autosave_every = args.next().and_then(|n| n.parse().ok())
+                }
+                "--decision-cache" => {
+                    options.decision_cache = args.next().and_then(|n| n.parse().ok())
+                }
+                "--profile" => options.profile = true,
+                "--print-board" => options.print_board = true,
+                "--capture-rate" => options.capture_rate = true,
+                "--start-paused" => options.start_paused = true,
+                "--fit-window" => options.fit_window = true,
+                "--noop-key" => {
+                    options.noop_key = match args.next().as_deref() {
+                        Some("PERIOD") => Some(KeyboardKey::KEY_PERIOD),
+                        Some("TAB") => Some(KeyboardKey::KEY_TAB),
+                        Some("N") => Some(KeyboardKey::KEY_N),
+                        Some("X") => Some(KeyboardKey::KEY_X),
+                        _ => {
+                            eprintln!("No-op key should be one of PERIOD, TAB, N or X");
+                            None
+                        }
+                    }
+                }
+                "--preview-every" => {
+                    options.preview_every = args.next().and_then(|n| n.parse().ok())
+                }
+                "--rng" => options.rng_name = args.next(),
+                "--seed" => options.seed = args.next().and_then(|n| n.parse().ok()),
+                "--delayed-growth" => options.config.delayed_growth = true,
+                "--eat-requires-action" => options.config.eat_requires_action = true,
+                "--practice-lives" => {
+                    options.config.practice = args
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .map(|lives| PracticeConfig { lives })
+                }
+                "--score-cap" => {
+                    options.config.score_cap = args.next().and_then(|n| n.parse().ok())
+                }
+                "--length-cap" => {
+                    options.config.length_cap = args.next().and_then(|n| n.parse().ok())
+                }
+                "--normalize-rewards" => options.config.normalize_rewards = true,
+                "--reward-clip" => {
+                    options.config.reward_clip =
+                        args.next().and_then(|c| c.parse().ok()).map(isize::abs)
+                }
+                "--grace-steps" => {
+                    options.config.spawn_grace_steps =
+                        args.next().and_then(|n| n.parse().ok()).unwrap_or(0)
+                }
+                "--random-start" => options.config.random_start_direction = true,
+                "--coop" => options.config.coop_takeover = true,
+                "--observation-delay" => {
+                    options.config.observation_delay =
+                        args.next().and_then(|n| n.parse().ok()).unwrap_or(0)
+                }
+                "--lives" => {
+                    options.config.respawn_lives =
+                        args.next().and_then(|n| n.parse().ok()).unwrap_or(0)
+                }
+                "--auto-restart" => {
+                    options.config.game_over = GameOverBehavior::AutoRestart {
+                        delay_frames: args.next().and_then(|n| n.parse().ok()).unwrap_or(60),
+                    }
+                }
+                "--menu-on-death" => options.config.game_over = GameOverBehavior::ReturnToMenu,
+                "--fog" => options.config.fog_radius = args.next().and_then(|r| r.parse().ok()),
+                "--action-repeat" => {
+                    options.config.action_repeat =
+                        args.next().and_then(|n| n.parse().ok()).unwrap_or(1)
+                }
+                "--shrink-powerups" => {
+                    options.config.shrink_amount =
+                        args.next().and_then(|n| n.parse().ok()).unwrap_or(0)
+                }
+                "--no-fruit" => options.config.disable_fruit = true,
+                "--start-length" => {
+                    options.config.start_length =
+                        args.next().and_then(|n| n.parse().ok()).unwrap_or(1)
+                }
+                "--exploration-bonus" => {
+                    options.config.exploration_bonus =
+                        args.next().and_then(|n| n.parse().ok()).unwrap_or(0)
+                }
+                "--exploration-half-life" => {
+                    options.config.exploration_half_life =
+                        args.next().and_then(|n| n.parse().ok()).unwrap_or(0)
+                }
+                "--coverage-bonus" => {
+                    options.config.coverage_bonus =
+                        args.next().and_then(|n| n.parse().ok()).unwrap_or(0)
+                }
+                "--line-bonus" => {
+                    options.config.line_bonus =
+                        args.next().and_then(|n| n.parse().ok()).unwrap_or(0)
+                }
+                "--spawn-protection" => options.config.spawn_protection = true,
+                "--tail-decay" => {
+                    options.config.tail_decay_interval = args.next().and_then(|n| n.parse().ok())
+                }
+                "--relocate-unreachable-fruit" => options.config.relocate_unreachable_fruit = true,
+                "--wrap" => options.config.walls = WallBehavior::Wrap,
+                "--bounce" => options.config.walls = WallBehavior::Bounce,
+                "--grow-at-head" => options.config.growth_side = GrowthSide::Head,
+                "--spawn-bias" => match args.next().as_deref() {
+                    Some("uniform") => options.config.spawn_bias = SpawnBias::Uniform,
+                    Some("center") => options.config.spawn_bias = SpawnBias::Center,
+                    Some("edge") => options.config.spawn_bias = SpawnBias::Edge,
+                    other => eprintln!("Unknown spawn bias: {:?}", other),
+                },
+                _ => eprintln!("Ignoring unknown argument: {}", arg),
+            }
+        }
+        drop_misfit_mask(&mut options.config);
+        drop_headless_pause(&mut options);
+
+        // Gameplay flags are dropped so everyone's daily scores are comparable
+        if options.daily {
+            let day = daily::today();
+            let seed = daily::seed_for(day);
+            println!("Daily Challenge {} (seed {})", daily::date_name(day), seed);
+            options.seed = Some(seed);
+            options.rng_name = None;
+            options.config = GameConfig::default();
+        }
+
+        // A seed on its own implies the standard generator
+        let seed = options.seed.unwrap_or(0);
+        match (options.rng_name.as_deref(), options.seed) {
+            (Some(name), _) => match RngBackend::from_name(name, seed) {
+                Some(backend) => options.config.rng = backend,
+                None => eprintln!("Unknown RNG backend: {}", name),
+            },
+            (None, Some(seed)) => options.config.rng = RngBackend::Std(seed),
+            (None, None) => (),
+        }
+        options
+    }
+    // Applies the settings that live outside of GameConfig
+    fn configure(&self, game_state: &mut GameState) {
+        if self.fruit_log.is_some() {
+            game_state.fruit_log = Some(Vec::new());
+        }
+        if self.latency_stats {
+            game_state.decision_latency = Some(Vec::new());
+        }
+        if self.profile {
+            game_state.profile = Some(Vec::new());
+        }
+        if self.decision_log.is_some() {
+            game_state.decision_log = Some(Vec::new());
+        }
+        if let Some(window) = &mut game_state.window {
+            self.configure_window(window);
+        }
+        match self.agent.as_deref() {
+            Some("greedy") => {
+                game_state.control_mode = Mode::Agent(Box::new(GreedyAgent::default()))
+            }
+            Some("floodfill") => game_state.control_mode = Mode::Agent(Box::new(FloodFillAgent)),
+            Some(name) => eprintln!("Unknown agent: {}", name),
+            None => (),
+        }
+        if let Some(capacity) = self.decision_cache {
+            if let Mode::Agent(agent) =
+                std::mem::replace(&mut game_state.control_mode, Mode::Keyboard)
+            {
+                game_state.control_mode = Mode::Agent(Box::new(CachedAgent::new(agent, capacity)));
+            }
+        }
+        if self.record.is_some() {
+            let interval = if self.keyframe_interval > 0 {
+                self.keyframe_interval
+            } else {
+                50
+            };
+            game_state.recording = Some(Replay::new(interval));
+        }
+        if let Some(path) = &self.replay {
+            match Replay::load(path) {
+                Ok(replay) => {
+                    game_state.seek(&replay, self.seek);
+                    let cursor = self.seek.min(replay.steps.len());
+                    game_state.control_mode = Mode::Replay { replay, cursor };
+                }
+                Err(e) => eprintln!("Failed to load replay {}: {}", path, e),
+            }
+        }
+    }
+    fn configure_window(&self, window: &mut WindowData) {
+        window.show_indices = self.show_indices;
+        window.show_axes = self.show_axes;
+        window.pause = self.start_paused;
+        window.fit_to_window = self.fit_window;
+        window.show_path = self.show_path;
+        window.trail_frames = self.trail_frames;
+        window.show_max_score = self.show_max_score;
+        window.daily = self.daily;
+        window.show_seams = self.show_seams;
+        window.theme = self.theme.clone();
+        if self.tail_color.is_some() {
+            window.theme.tail = self.tail_color;
+        }
+        if let Some(key) = self.noop_key {
+            window.noop_key = key;
+        }
+        if self.record_input.is_some() {
+            window.input_log = Some(Vec::new());
+        }
+        if let Some(path) = &self.autosave {
+            if let Some(every) = self.autosave_every {
+                window.autosave_every = every;
+            }
+            // A save from a finished game isn't worth offering
+            if std::path::Path::new(path).exists() {
+                match load_game(path) {
+                    Ok(snapshot) if !snapshot.game_over => {
+                        window.resume_offer = Some(snapshot);
+                        window.pause = true;
+                    }
+                    Ok(_) => (),
+                    Err(e) => eprintln!("Failed to load autosave {}: {}", path, e),
+                }
+            }
+            window.autosave = Some(path.clone());
+        }
+        if let Some(path) = &self.play_input {
+            match load_input_log(path, window.noop_key) {
+                Ok(events) => window.input_playback = Some(events),
+                Err(e) => eprintln!("Failed to load input log {}: {}", path, e),
+            }
+        }
+    }
+    // Writes out whatever was collected while the game ran
+    fn finish(&self, game_state: &GameState) {
+        if let (Some(path), Some(window)) = (&self.record_input, &game_state.window) {
+            if let Some(log) = &window.input_log {
+                if let Err(e) = save_input_log(path, log, window.noop_key) {
+                    eprintln!("Failed to write input log to {}: {}", path, e);
+                }
+            }
+        }
+        if let Some((mean, p95)) = game_state.latency_stats() {
+            println!("Decision latency: mean {:?}, p95 {:?}", mean, p95);
+        }
+        if let Some(shares) = game_state.profile_shares() {
+            for (name, share) in shares {
+                println!("{:>14}: {:5.1}%", name, share);
+            }
+        }
+        if let Some(path) = &self.fruit_log {
+            write_fruit_log(path, game_state);
+        }
+        if let (Some(path), Some(log)) = (&self.decision_log, &game_state.decision_log) {
+            let text: String = log.iter().map(|line| format!("{}\n", line)).collect();
+            if let Err(e) = std::fs::write(path, text) {
+                eprintln!("Failed to write decision log to {}: {}", path, e);
+            }
+        }
+        if let (Some(path), Some(recording)) = (&self.record, &game_state.recording) {
+            if let Err(e) = recording.save(path) {
+                eprintln!("Failed to write replay to {}: {}", path, e);
+            }
+        }
+    }
+}
+
+// Decision as a JSON object on one line, written by hand to keep the dependency list short
+// Backward moves are always refused by the reversal guard, so they're marked blocked
+fn decision_record(step: usize, obs: &Observation, mv: Move) -> String {
+    let cell = |cell: Option<(isize, isize)>| match cell {
+        Some((x, y)) => format!("[{},{}]", x, y),
+        None => "null".to_string(),
+    };
+    format!(
+        "{{\"step\":{},\"head\":{},\"fruit\":{},\"velocity\":[{},{}],\"length\":{},\"move\":\"{}\",\"blocked\":{}}}",
+        step,
+        cell(obs.snake.first().copied()),
+        cell(obs.fruit),
+        obs.velocity.0,
+        obs.velocity.1,
+        obs.snake.len(),
+        mv.name(),
+        matches!(mv, Move::BW)
+    )
+}
+
+fn write_fruit_log(path: &str, game_state: &GameState) {
+    // One CSV row per board row, each column holding the spawn count for that cell
+    let table = game_state
+        .fruit_spawn_frequencies()
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|count| count.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if let Err(e) = std::fs::write(path, table + "\n") {
+        eprintln!("Failed to write fruit log to {}: {}", path, e);
+    }
+}
+
+// Window hints asked for on the command line, on top of the always resizable window
+fn window_flags(options: &Options) -> Vec<ConfigFlags> {
+    let mut flags = vec![ConfigFlags::FLAG_WINDOW_RESIZABLE];
+    if options.vsync {
+        flags.push(ConfigFlags::FLAG_VSYNC_HINT);
+    }
+    if options.msaa {
+        flags.push(ConfigFlags::FLAG_MSAA_4X_HINT);
+    }
+    flags
+}
+
+fn open_window(options: &Options) -> (RaylibHandle, RaylibThread) {
+    let mut builder = raylib::init();
+    builder
+        .size(800, 600)
+        //.undecorated()
+        .title("Test");
+    // Hints only take effect when set before the window is built
+    for flag in window_flags(options) {
+        match flag {
+            ConfigFlags::FLAG_WINDOW_RESIZABLE => builder.resizable(),
+            ConfigFlags::FLAG_VSYNC_HINT => builder.vsync(),
+            ConfigFlags::FLAG_MSAA_4X_HINT => builder.msaa_4x(),
+            _ => &mut builder,
+        };
+    }
+    let (mut rl, thread) = builder.build();
+
+    rl.set_target_fps(60);
+    (rl, thread)
+}
+
+fn main() {
+    let options = Options::from_args();
+
+    if let Some(path) = &options.validate_replay {
+        let mut game_state = GameState::init(None, options.config.clone());
+        match Replay::load(path) {
+            Ok(replay) => match game_state.validate_replay(&replay) {
+                Ok(()) => println!("Replay {} matches the current rules", path),
+                Err(divergence) => {
+                    eprintln!("Replay {} {}", path, divergence);
+                    std::process::exit(1);
+                }
+            },
+            Err(e) => eprintln!("Failed to load replay {}: {}", path, e),
+        }
+        return;
+    }
+
+    if let Some(path) = &options.verify_digest {
+        let mut game_state = GameState::init(None, options.config.clone());
+        match Replay::load(path) {
+            Ok(replay) if game_state.verify_digest(&replay) => {
+                println!("Replay {} matches its digest", path)
+            }
+            Ok(_) => {
+                eprintln!("Replay {} does not match its digest", path);
+                std::process::exit(1);
+            }
+            Err(e) => eprintln!("Failed to load replay {}: {}", path, e),
+        }
+        return;
+    }
+
+    if let Some(dir) = &options.render_frames {
+        let Some(path) = &options.replay else {
+            eprintln!("--render-frames needs a --replay to render");
+            return;
+        };
+        let mut game_state = GameState::init(None, options.config.clone());
+        match Replay::load(path) {
+            Ok(replay) => {
+                let states = game_state.replay_states(&replay);
+                let fps = options.render_fps.unwrap_or(60);
+                match frames::render_replay_to_frames(
+                    &states,
+                    game_state.board_size,
+                    &options.theme,
+                    dir,
+                    fps,
+                ) {
+                    Ok(written) => println!("Wrote {} frames to {}", written, dir),
+                    Err(e) => eprintln!("Failed to render frames to {}: {}", dir, e),
+                }
+            }
+            Err(e) => eprintln!("Failed to load replay {}: {}", path, e),
+        }
+        return;
+    }
+
+    if let Some(steps) = options.headless {
+        // Declared first so attached windows outlive the game borrowing them
+        let mut raylib_window = None;
+        let mut preview_window = None;
+        let mut game_state = GameState::init(None, options.config.clone());
+        options.configure(&mut game_state);
+        // A live preview needs the window for the whole run, so it sits out --attach-after
+        if let Some(every) = options
+            .preview_every
+            .filter(|_| options.attach_after.is_none())
+        {
+            let (rl, thread) = preview_window.insert(open_window(&options));
+            game_state.attach_window(rl, thread);
+            if let Some(window) = &mut game_state.window {
+                options.configure_window(window);
+                window.preview_every = every;
+            }
+        }
+        let attach_at = options.attach_after.unwrap_or(steps).min(steps);
+        let mut length = if let Mode::Keyboard = game_state.control_mode {
+            // Headless runs need something to steer, so fall back to the greedy agent
+            game_state.run_headless_with(GreedyAgent::default(), attach_at)
+        } else {
+            game_state.run_headless(attach_at)
+        };
+        if options.attach_after.is_some() && !game_state.game_over {
+            // Watch the game live until the window is closed, then carry on without it
+            let (rl, thread) = raylib_window.insert(open_window(&options));
+            game_state.attach_window(rl, thread);
+            if let Some(window) = &mut game_state.window {
+                options.configure_window(window);
+            }
+            game_state.run_as_game();
+            game_state.detach_window();
+            length = game_state.run_headless(steps - attach_at);
+        }
+        println!(
+            "Final snake length: {}, score: {}, coverage: {:.1}%",
+            length,
+            game_state.score,
+            game_state.coverage()
+        );
+        if game_state.config.exploration_bonus != 0 {
+            println!("Exploration score: {}", game_state.exploration_score);
+        }
+        if options.capture_rate {
+            match game_state.fruit_capture_rate() {
+                Some(rate) => println!(
+                    "Fruit capture rate: {:.1}% ({} of {})",
+                    100.0 * rate,
+                    game_state.fruits_eaten,
+                    game_state.fruits_spawned
+                ),
+                None => println!("Fruit capture rate: no fruit spawned"),
+            }
+        }
+        if game_state.solved {
+            println!("Solved: reached the configured cap");
+        }
+        if options.print_board {
+            print!("{}", game_state.to_debug_string());
+        }
+
+        options.finish(&game_state);
+        return;
+    }
+
+    let (mut rl, mut thread) = open_window(&options);
+    let mut game_state = GameState::init(Some((&mut rl, &mut thread)), options.config.clone());
+    options.configure(&mut game_state);
+    game_state.run_as_game();
+
+    options.finish(&game_state);
+
+    println!("Hello, world!");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(config: GameConfig) -> GameState<'static> {
+        GameState::init(None, config)
+    }
+
+    #[test]
+    fn uniform_spawns_cover_free_cells_evenly() {
+        let mut game = game(GameConfig {
+            board_size: Some((4, 4)),
+            rng: RngBackend::Std(7),
+            ..Default::default()
+        });
+        game.fruit_log = Some(Vec::new());
+        let draws = 15_000;
+        for _ in 0..draws {
+            game.fruit_position = None;
+            game.spawn_fruit((0, 0));
+        }
+
+        let table = game.fruit_spawn_frequencies();
+        assert_eq!(table.iter().flatten().sum::<usize>(), draws);
+        // The head sits on (0, 0), leaving 15 free cells
+        assert_eq!(table[0][0], 0);
+        let expected = draws / 15;
+        for (y, row) in table.iter().enumerate() {
+            for (x, &count) in row.iter().enumerate() {
+                if (x, y) != (0, 0) {
+                    assert!(
+                        count.abs_diff(expected) < expected / 10,
+                        "cell ({}, {}) drawn {} times, expected about {}",
+                        x,
+                        y,
+                        count,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn frequency_table_counts_logged_spawns() {
+        let mut game = game(GameConfig {
+            board_size: Some((3, 2)),
+            ..Default::default()
+        });
+        game.fruit_log = Some(vec![(2, 1), (0, 1), (2, 1)]);
+        assert_eq!(
+            game.fruit_spawn_frequencies(),
+            vec![vec![0, 0, 0], vec![1, 0, 2]]
+        );
+    }
+
+    // Length after each of a few steps, the first of them eating a fruit right ahead
+    fn lengths_after_eating(delayed_growth: bool) -> Vec<usize> {
+        let mut game = game(GameConfig {
+            delayed_growth,
+            start_length: 3,
+            ..Default::default()
+        });
+        game.fruit_position = Some((3, 0));
+        (0..3)
+            .map(|_| {
+                game.step(Move::FW);
+                // Later fruit is kept out of the way
+                game.fruit_position = Some((15, 15));
+                game.snake_position.len()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn delayed_growth_lags_instant_growth_by_a_step() {
+        assert_eq!(lengths_after_eating(false), vec![4, 4, 4]);
+        assert_eq!(lengths_after_eating(true), vec![3, 4, 4]);
+    }
+
+    fn seeded(seed: u64) -> GameState<'static> {
+        game(GameConfig {
+            rng: RngBackend::Std(seed),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn different_agents_drive_the_same_game() {
+        let agents: Vec<fn() -> Box<dyn Agent>> = vec![|| Box::new(GreedyAgent::default()), || {
+            Box::new(FloodFillAgent)
+        }];
+        for make_agent in agents {
+            let mut runs = (0..2).map(|_| {
+                let mut game = seeded(3);
+                game.control_mode = Mode::Agent(make_agent());
+                let length = game.run_headless(200);
+                (length, game.score, game.steps_taken)
+            });
+            let first = runs.next().unwrap();
+            assert!(first.2 > 0);
+            assert!(first.1 > 0, "agent never ate");
+            // Same seed and agent give the same game
+            assert_eq!(Some(first), runs.next());
+        }
+    }
+
+    // Average ring distance from the middle of a 9x9 board over many seeded spawns
+    fn mean_spawn_distance(spawn_bias: SpawnBias) -> f64 {
+        let mut game = game(GameConfig {
+            board_size: Some((9, 9)),
+            spawn_bias,
+            rng: RngBackend::Std(11),
+            ..Default::default()
+        });
+        let draws = 5_000;
+        let mut total = 0;
+        for _ in 0..draws {
+            game.fruit_position = None;
+            game.spawn_fruit((0, 0));
+            let (x, y) = game.fruit_position.unwrap();
+            total += (x - 4).abs().max((y - 4).abs());
+        }
+        total as f64 / draws as f64
+    }
+
+    #[test]
+    fn center_bias_spawns_closer_to_the_middle() {
+        let uniform = mean_spawn_distance(SpawnBias::Uniform);
+        let center = mean_spawn_distance(SpawnBias::Center);
+        let edge = mean_spawn_distance(SpawnBias::Edge);
+        assert!(
+            center < uniform - 0.2,
+            "center {} uniform {}",
+            center,
+            uniform
+        );
+        assert!(edge > uniform + 0.2, "edge {} uniform {}", edge, uniform);
+    }
+
+    #[test]
+    fn each_direction_moves_the_head_one_cell() {
+        let mut game = game(GameConfig {
+            start_length: 3,
+            start_cell: Some((8, 8)),
+            ..Default::default()
+        });
+        game.fruit_position = Some((0, 15));
+        // Heading right to start with, each right turn swings the heading clockwise
+        let moves = [
+            (Move::FW, (1, 0)),
+            (Move::RT, (0, 1)),
+            (Move::RT, (-1, 0)),
+            (Move::RT, (0, -1)),
+        ];
+        for (mv, delta) in moves {
+            let before = game.snake_position.clone();
+            let score = game.score;
+            game.step(mv);
+            let head = game.snake_position[0];
+            assert_eq!(head, (before[0].0 + delta.0, before[0].1 + delta.1));
+            assert_eq!(game.snake_position[1..], before[..before.len() - 1]);
+            // No step penalty in this game, so moving alone leaves the score alone
+            assert_eq!(game.score, score);
+            assert!(!game.game_over);
+        }
+    }
+
+    fn snake_after_eating(growth_side: GrowthSide) -> Vec<(isize, isize)> {
+        let mut game = game(GameConfig {
+            growth_side,
+            start_length: 3,
+            ..Default::default()
+        });
+        game.fruit_position = Some((3, 0));
+        game.step(Move::FW);
+        game.snake_position
+    }
+
+    #[test]
+    fn growth_side_picks_the_end_that_grows() {
+        assert_eq!(
+            snake_after_eating(GrowthSide::Tail),
+            vec![(3, 0), (2, 0), (1, 0), (0, 0)]
+        );
+        // The new head goes one cell further along the heading, still at index 0
+        assert_eq!(
+            snake_after_eating(GrowthSide::Head),
+            vec![(4, 0), (3, 0), (2, 0), (1, 0)]
+        );
+    }
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("ai_snake_{}_{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned()
     }
-}
 
-fn main() {
-    let (mut rl, mut thread) = raylib::init()
-        .size(800, 600)
-        .resizable()
-        //.undecorated()
-        .title("Test")
-        .build();
+    fn scoring_game() -> GameState<'static> {
+        game(GameConfig {
+            rng: RngBackend::Std(5),
+            coverage_bonus: 100,
+            line_bonus: 10,
+            shrink_amount: 1,
+            ..Default::default()
+        })
+    }
 
-    rl.set_target_fps(60);
-    let mut game_state = GameState::init(Some((&mut rl, &mut thread)));
-    game_state.run_as_game();
+    #[test]
+    fn keyframes_reconstruct_intermediate_states() {
+        let mut recorded = scoring_game();
+        recorded.recording = Some(Replay::new(5));
+        recorded.run_headless_with(GreedyAgent::default(), 40);
+        let path = temp_path("keyframes.replay");
+        recorded.recording.as_ref().unwrap().save(&path).unwrap();
+        let replay = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(replay.keyframes.len() > 2);
 
-    println!("Hello, world!");
+        for step in [3, 7, 23, replay.steps.len()] {
+            let mut expected = scoring_game();
+            expected.run_headless_with(GreedyAgent::default(), step);
+            let mut seeking = scoring_game();
+            seeking.seek(&replay, step);
+            // Seeking takes its fruit from the recording, leaving the generator alone
+            let actual = Snapshot {
+                rng_state: expected.rng.state(),
+                ..seeking.snapshot()
+            };
+            assert!(actual == expected.snapshot(), "step {} differs", step);
+            assert_eq!(seeking.score, expected.score);
+        }
+    }
+
+    #[test]
+    fn reset_starts_a_new_recording() {
+        let mut game = scoring_game();
+        game.recording = Some(Replay::new(5));
+        game.run_headless_with(GreedyAgent::default(), 10);
+        game.reset();
+        let recording = game.recording.as_ref().unwrap();
+        assert!(recording.steps.is_empty() && recording.keyframes.is_empty());
+        assert_eq!(recording.interval, 5);
+    }
+
+    fn wrapping_game() -> GameState<'static> {
+        let mut game = game(GameConfig {
+            board_size: Some((5, 5)),
+            walls: WallBehavior::Wrap,
+            ..Default::default()
+        });
+        game.fruit_position = Some((0, 4));
+        game
+    }
+
+    #[test]
+    fn body_follows_the_head_across_the_edge() {
+        let mut game = wrapping_game();
+        game.snake_position = vec![(4, 2), (3, 2), (2, 2)];
+        game.step(Move::FW);
+        assert_eq!(game.snake_position, vec![(0, 2), (4, 2), (3, 2)]);
+        game.step(Move::FW);
+        assert_eq!(game.snake_position, vec![(1, 2), (0, 2), (4, 2)]);
+        game.step(Move::LT);
+        assert_eq!(game.snake_position, vec![(1, 1), (1, 2), (0, 2)]);
+        assert!(!game.game_over);
+    }
+
+    #[test]
+    fn practice_death_rewinds_to_the_last_meal() {
+        let mut game = game(GameConfig {
+            practice: Some(PracticeConfig { lives: 2 }),
+            start_length: 3,
+            ..Default::default()
+        });
+        game.fruit_position = Some((3, 0));
+        game.step(Move::FW);
+        let after_meal = game.snapshot();
+        assert_eq!(game.score, 1);
+
+        // Turning up off the top row is fatal, each time from the same checkpoint
+        for lives in [1, 0] {
+            game.step(Move::LT);
+            assert!(!game.game_over);
+            assert_eq!(game.lives, lives);
+            assert_eq!(game.snake_position, after_meal.snake);
+            assert_eq!(game.snake_velocity, after_meal.velocity);
+            assert_eq!(game.score, 1);
+            game.fruit_position = Some((15, 15));
+        }
+        game.step(Move::LT);
+        assert!(game.game_over);
+    }
+
+    #[test]
+    fn protected_spawns_avoid_the_next_head_cell() {
+        let mut protected = game(GameConfig {
+            board_size: Some((4, 4)),
+            spawn_protection: true,
+            rng: RngBackend::Std(2),
+            ..Default::default()
+        });
+        for _ in 0..2_000 {
+            protected.fruit_position = None;
+            protected.spawn_fruit((0, 0));
+            assert_ne!(protected.fruit_position, Some((1, 0)));
+        }
+
+        // With nowhere else to go the fruit still spawns ahead of the head
+        let mut tiny = game(GameConfig {
+            board_size: Some((2, 1)),
+            spawn_protection: true,
+            ..Default::default()
+        });
+        tiny.spawn_fruit((0, 0));
+        assert_eq!(tiny.fruit_position, Some((1, 0)));
+    }
+
+    #[test]
+    fn segment_index_is_centered_in_its_cell() {
+        // A 31 pixel cell leaves 10 pixels either side of an 11 pixel wide label
+        assert_eq!(centered_text(62, 31, 31, 11, 10), (72, 41));
+        assert_eq!(centered_text(0, 0, 31, 31, 31), (0, 0));
+    }
+
+    fn fruit_sequence(rng: RngBackend) -> Vec<(isize, isize)> {
+        let mut game = game(GameConfig {
+            rng,
+            ..Default::default()
+        });
+        (0..20)
+            .map(|_| {
+                game.fruit_position = None;
+                game.spawn_fruit((0, 0));
+                game.fruit_position.unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn seeded_backends_repeat_their_fruit() {
+        for name in ["std", "small", "counter"] {
+            let rng = |seed| RngBackend::from_name(name, seed).unwrap();
+            assert!(fruit_sequence(rng(9)) == fruit_sequence(rng(9)), "{}", name);
+            assert!(
+                fruit_sequence(rng(9)) != fruit_sequence(rng(10)),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn latency_covers_a_slow_agent() {
+        let delay = std::time::Duration::from_millis(5);
+        let mut game = game(GameConfig::default());
+        game.decision_latency = Some(Vec::new());
+        game.run_headless_with(
+            move |_: &Observation| {
+                std::thread::sleep(delay);
+                Move::FW
+            },
+            5,
+        );
+        assert_eq!(game.decision_latency.as_ref().unwrap().len(), 5);
+        let (mean, p95) = game.latency_stats().unwrap();
+        assert!(mean >= delay && mean < delay * 20, "mean {:?}", mean);
+        assert!(p95 >= mean);
+    }
+
+    #[test]
+    fn full_row_pays_its_bonus_once() {
+        let mut game = game(GameConfig {
+            board_size: Some((4, 4)),
+            line_bonus: 10,
+            ..Default::default()
+        });
+        game.snake_position = vec![(2, 0), (1, 0), (0, 0)];
+        game.pending_growth = 2;
+        game.fruit_position = Some((0, 3));
+        game.step(Move::FW);
+        assert_eq!(game.score, 10);
+        // The row stays full while the snake turns down, without paying again
+        game.step(Move::RT);
+        assert_eq!(game.snake_position[1..], [(3, 0), (2, 0), (1, 0), (0, 0)]);
+        assert_eq!(game.score, 10);
+        assert_eq!(game.credited_lines.0, vec![true, false, false, false]);
+    }
+
+    #[test]
+    fn auto_restart_resets_after_the_delay() {
+        let mut restarting = game(GameConfig {
+            start_length: 2,
+            game_over: GameOverBehavior::AutoRestart { delay_frames: 4 },
+            ..Default::default()
+        });
+        restarting.fruit_position = Some((15, 15));
+        restarting.step(Move::LT);
+        assert!(restarting.game_over);
+
+        let (mut frames, mut in_menu) = (0, false);
+        for _ in 0..3 {
+            restarting.game_over_frame(&mut frames, false, &mut in_menu);
+            assert!(restarting.game_over);
+        }
+        restarting.game_over_frame(&mut frames, false, &mut in_menu);
+        assert!(!restarting.game_over);
+        assert_eq!(restarting.snake_position, vec![(1, 0), (0, 0)]);
+        assert_eq!(restarting.steps_taken, 0);
+        assert_eq!(frames, 0);
+    }
+
+    #[test]
+    fn valid_games_keep_the_invariants() {
+        let configs = [
+            GameConfig::default(),
+            GameConfig {
+                walls: WallBehavior::Wrap,
+                ..Default::default()
+            },
+            GameConfig {
+                walls: WallBehavior::Bounce,
+                start_length: 4,
+                ..Default::default()
+            },
+            GameConfig {
+                delayed_growth: true,
+                growth_side: GrowthSide::Head,
+                ..Default::default()
+            },
+            GameConfig {
+                growth_side: GrowthSide::Head,
+                tail_decay_interval: Some(7),
+                board_size: Some((12, 7)),
+                ..Default::default()
+            },
+        ];
+        for (seed, config) in configs.into_iter().enumerate() {
+            for agent in 0..2 {
+                let mut game = game(GameConfig {
+                    rng: RngBackend::Std(seed as u64),
+                    ..config.clone()
+                });
+                // Debug builds check the invariants after every move, panicking on a violation
+                if agent == 0 {
+                    game.run_headless_with(GreedyAgent::default(), 500);
+                } else {
+                    game.run_headless_with(FloodFillAgent, 500);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Snake invariant broken")]
+    fn overlapping_snake_trips_the_invariants() {
+        let mut game = game(GameConfig::default());
+        game.snake_position = vec![(1, 1), (2, 1), (2, 2), (1, 2), (1, 1)];
+        game.steps_taken = 1;
+        game.check_invariants();
+    }
+
+    // A Raylib handle can't be created without opening a real window, so this sticks to
+    // the windowless side of the handoff: dropping the window and drawing without one
+    #[test]
+    fn detaching_between_steps_leaves_the_game_alone() {
+        let mut steady = seeded(8);
+        steady.run_headless_with(GreedyAgent::default(), 30);
+
+        let mut toggled = seeded(8);
+        toggled.control_mode = Mode::Agent(Box::new(GreedyAgent::default()));
+        for _ in 0..3 {
+            toggled.run_headless(10);
+            toggled.detach_window();
+            toggled.draw_game();
+            assert!(toggled.window.is_none());
+        }
+        assert!(toggled.snapshot() == steady.snapshot());
+    }
+
+    #[test]
+    fn forward_closure_runs_into_the_right_wall() {
+        let heads = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let seen = heads.clone();
+        let mut game = game(GameConfig {
+            disable_fruit: true,
+            ..Default::default()
+        });
+        game.run_headless_with(
+            move |obs: &Observation| {
+                seen.borrow_mut().push(obs.snake[0]);
+                Move::FW
+            },
+            100,
+        );
+        assert!(game.game_over);
+        assert_eq!(game.steps_taken, 16);
+        let expected: Vec<_> = (0..16).map(|x| (x, 0)).collect();
+        assert_eq!(*heads.borrow(), expected);
+    }
+
+    #[test]
+    fn covering_half_the_board_reports_half() {
+        let mut game = game(GameConfig {
+            board_size: Some((4, 4)),
+            start_length: 2,
+            ..Default::default()
+        });
+        game.fruit_position = Some((3, 3));
+        assert_eq!(game.coverage(), 12.5);
+        // Along the top row, then back along the second one
+        for mv in [Move::FW, Move::FW, Move::RT, Move::RT, Move::FW, Move::FW] {
+            game.step(mv);
+        }
+        assert_eq!(game.coverage(), 50.0);
+    }
+
+    #[test]
+    fn returning_to_the_start_pays_no_exploration_bonus() {
+        let mut game = game(GameConfig {
+            exploration_bonus: 1,
+            ..Default::default()
+        });
+        game.fruit_position = Some((15, 15));
+        for mv in [Move::FW, Move::RT, Move::RT, Move::RT] {
+            game.step(mv);
+        }
+        assert_eq!(game.snake_position[0], (0, 0));
+        assert_eq!(game.exploration_score, 3);
+    }
+
+    #[test]
+    fn window_flags_follow_the_options() {
+        let flags = |vsync, msaa| {
+            window_flags(&Options {
+                vsync,
+                msaa,
+                ..Default::default()
+            })
+        };
+        assert_eq!(
+            flags(false, false),
+            vec![ConfigFlags::FLAG_WINDOW_RESIZABLE]
+        );
+        assert_eq!(
+            flags(true, true),
+            vec![
+                ConfigFlags::FLAG_WINDOW_RESIZABLE,
+                ConfigFlags::FLAG_VSYNC_HINT,
+                ConfigFlags::FLAG_MSAA_4X_HINT
+            ]
+        );
+        assert_eq!(
+            flags(false, true),
+            vec![
+                ConfigFlags::FLAG_WINDOW_RESIZABLE,
+                ConfigFlags::FLAG_MSAA_4X_HINT
+            ]
+        );
+    }
+
+    // Plays `frames` keyboard frames, reading a key only while the turn is still open like
+    // the window does, and returns the keys read along with their frame
+    fn play_keys(
+        game: &mut GameState,
+        frames: usize,
+        mut read_key: impl FnMut(usize) -> Option<KeyboardKey>,
+    ) -> Vec<(usize, KeyboardKey)> {
+        let mut log = Vec::new();
+        let mut allow_move = true;
+        for frame in 0..frames {
+            let key = if allow_move { read_key(frame) } else { None };
+            if let Some(key) = key {
+                log.push((frame, key));
+            }
+            game.keyboard_frame(frame, key, KeyboardKey::KEY_N, false, &mut allow_move);
+        }
+        log
+    }
+
+    #[test]
+    fn replayed_input_log_reproduces_the_game() {
+        let pressed = |frame| match frame {
+            3 => Some(KeyboardKey::KEY_DOWN),
+            // Ignored, the turn for this step is already used up
+            5 => Some(KeyboardKey::KEY_LEFT),
+            24 => Some(KeyboardKey::KEY_RIGHT),
+            31 => Some(KeyboardKey::KEY_N),
+            47 => Some(KeyboardKey::KEY_DOWN),
+            66 => Some(KeyboardKey::KEY_LEFT),
+            _ => None,
+        };
+        let mut live = seeded(4);
+        let log = play_keys(&mut live, 90, pressed);
+        assert_eq!(log.len(), 5);
+
+        let path = temp_path("input.log");
+        save_input_log(&path, &log, KeyboardKey::KEY_N).unwrap();
+        let mut events = load_input_log(&path, KeyboardKey::KEY_N).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(events, log);
+
+        let mut replayed = seeded(4);
+        play_keys(&mut replayed, 90, |frame| {
+            next_logged_key(&mut events, frame)
+        });
+        assert!(events.is_empty());
+        assert!(replayed.snapshot() == live.snapshot());
+        assert!(live.steps_taken > 5 && !live.game_over);
+    }
+
+    #[test]
+    fn only_the_first_turn_of_a_step_counts() {
+        let mut game = game(GameConfig {
+            start_length: 3,
+            start_cell: Some((8, 8)),
+            ..Default::default()
+        });
+        game.fruit_position = Some((0, 15));
+        // A second left turn would fold the snake back into its neck
+        game.apply_move(Move::LT);
+        game.apply_move(Move::LT);
+        assert_eq!(game.snake_velocity, (0, -1));
+        game.step(Move::FW);
+        assert_eq!(game.snake_position[0], (8, 7));
+
+        // Same through the keyboard's absolute directions
+        turn(&mut game.snake_velocity, &mut game.turned_this_step, (1, 0));
+        turn(&mut game.snake_velocity, &mut game.turned_this_step, (0, 1));
+        game.step(Move::FW);
+        assert_eq!(game.snake_position[0], (9, 7));
+        assert!(!game.game_over);
+    }
+
+    #[test]
+    fn without_fruit_the_length_never_changes() {
+        let mut survivor = game(GameConfig {
+            disable_fruit: true,
+            start_length: 4,
+            rng: RngBackend::Std(1),
+            ..Default::default()
+        });
+        survivor.run_headless_with(
+            |obs: &Observation| {
+                assert_eq!(obs.snake.len(), 4);
+                assert_eq!(obs.fruit, None);
+                FloodFillAgent.decide(obs)
+            },
+            300,
+        );
+        assert_eq!(survivor.snake_position.len(), 4);
+        assert_eq!(survivor.fruits_spawned, 0);
+
+        // Only a collision ends it, every step before that scoring a point
+        let mut crasher = game(GameConfig {
+            disable_fruit: true,
+            start_length: 4,
+            ..Default::default()
+        });
+        crasher.run_headless_with(|_: &Observation| Move::FW, 300);
+        assert!(crasher.game_over);
+        assert_eq!(crasher.snake_position[0], (16, 0));
+        assert_eq!(crasher.score, 12);
+    }
+
+    #[test]
+    fn every_demo_starts_in_the_open() {
+        for demo in demo::DEMOS {
+            let game = game(GameConfig {
+                board_size: Some(demo.board_size),
+                start_length: demo.start_length,
+                rng: RngBackend::Std(demo.seed),
+                ..Default::default()
+            });
+            assert!(demo::find(demo.name).is_some());
+            assert!(
+                matches!(demo.agent, "greedy" | "floodfill"),
+                "{}",
+                demo.name
+            );
+            assert_eq!(
+                game.snake_position.len(),
+                demo.start_length,
+                "{}",
+                demo.name
+            );
+            assert!(game
+                .snake_position
+                .iter()
+                .all(|&cell| game.is_playable(cell)));
+            // The whole board is open to the head from its first move on
+            let next = game.ahead_of(game.snake_position[0]);
+            assert!(game.is_playable(next), "{}", demo.name);
+            assert!(game.reachable_free_cells(next) >= game.free_cells().len());
+        }
+    }
+
+    #[test]
+    fn score_pulse_shrinks_back_to_normal() {
+        assert_eq!(pulse_font_size(20, PULSE_FRAMES), 20 + PULSE_EXTRA_SIZE);
+        assert_eq!(pulse_font_size(20, PULSE_FRAMES * 3), 20 + PULSE_EXTRA_SIZE);
+        assert_eq!(pulse_font_size(20, 0), 20);
+        let sizes: Vec<_> = (0..=PULSE_FRAMES)
+            .rev()
+            .map(|left| pulse_font_size(20, left))
+            .collect();
+        assert!(sizes.windows(2).all(|pair| pair[1] <= pair[0]));
+    }
+
+    #[test]
+    fn head_reaches_the_open_board() {
+        let game = game(GameConfig {
+            board_size: Some((6, 5)),
+            start_length: 3,
+            ..Default::default()
+        });
+        // Everything but the head and the neck, the tail moving off its cell in time
+        assert_eq!(game.reachable_free_cells(game.snake_position[0]), 28);
+        assert_eq!(game.reachable_free_cells((5, 4)), 28);
+    }
+
+    #[test]
+    fn sealed_pocket_counts_only_its_own_cells() {
+        let mut game = game(GameConfig {
+            board_size: Some((6, 5)),
+            ..Default::default()
+        });
+        // The body walls off the two cells left of it in the top rows
+        game.snake_position = vec![(0, 2), (1, 2), (2, 2), (2, 1), (2, 0), (3, 0)];
+        game.snake_velocity = (-1, 0);
+        assert_eq!(game.reachable_free_cells((0, 0)), 4);
+        assert_eq!(game.reachable_free_cells((5, 4)), 21);
+    }
+
+    fn length_after_shrinking(start_length: usize) -> usize {
+        let mut game = game(GameConfig {
+            shrink_amount: 2,
+            start_length,
+            ..Default::default()
+        });
+        game.fruit_position = Some((15, 15));
+        game.shrink_powerups = vec![(start_length as isize, 0)];
+        game.step(Move::FW);
+        assert!(!game.shrink_powerups.contains(&game.snake_position[0]));
+        game.snake_position.len()
+    }
+
+    #[test]
+    fn shrink_powerup_cuts_the_tail() {
+        assert_eq!(length_after_shrinking(5), 3);
+        // Never below the head alone
+        assert_eq!(length_after_shrinking(2), 1);
+    }
+
+    #[test]
+    fn repeated_action_asks_every_third_step() {
+        let asked = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let seen = asked.clone();
+        let mut game = game(GameConfig {
+            action_repeat: 3,
+            start_cell: Some((8, 8)),
+            disable_fruit: true,
+            ..Default::default()
+        });
+        game.run_headless_with(
+            move |obs: &Observation| {
+                seen.borrow_mut().push(obs.snake[0]);
+                Move::LT
+            },
+            9,
+        );
+        // Each left turn is followed by two steps straight on
+        assert_eq!(*asked.borrow(), vec![(8, 8), (8, 5), (5, 5)]);
+        assert_eq!(game.snake_position[0], (5, 8));
+    }
+
+    #[test]
+    fn vacated_tint_fades_out_linearly() {
+        assert_eq!(fade_alpha(8, 8), 1.0);
+        assert_eq!(fade_alpha(6, 8), 0.75);
+        assert_eq!(fade_alpha(2, 8), 0.25);
+        assert_eq!(fade_alpha(0, 8), 0.0);
+        assert_eq!(fade_alpha(20, 8), 1.0);
+        assert_eq!(fade_alpha(3, 0), 0.0);
+    }
+
+    #[test]
+    fn max_score_fills_the_rest_of_the_board() {
+        let max_score = |config| game(config).max_possible_score();
+        assert_eq!(max_score(GameConfig::default()), 255 * FRUIT_REWARD);
+        assert_eq!(
+            max_score(GameConfig {
+                board_size: Some((5, 4)),
+                start_length: 3,
+                ..Default::default()
+            }),
+            17 * FRUIT_REWARD
+        );
+    }
+
+    #[test]
+    fn fog_hides_cells_beyond_the_radius() {
+        let mut game = game(GameConfig {
+            board_size: Some((6, 5)),
+            fog_radius: Some(1),
+            ..Default::default()
+        });
+        game.snake_position = vec![(2, 2), (1, 2)];
+        game.fruit_position = Some((5, 4));
+        let grid = game.observe().grid();
+        for (y, row) in grid.iter().enumerate() {
+            for (x, &cell) in row.iter().enumerate() {
+                let near = x.abs_diff(2) <= 1 && y.abs_diff(2) <= 1;
+                assert_eq!(cell == agent::UNKNOWN, !near, "({}, {})", x, y);
+            }
+        }
+        assert_eq!(grid[2][2], agent::HEAD);
+        assert_eq!(grid[2][1], agent::BODY);
+    }
+
+    #[test]
+    fn fog_doesnt_hide_the_body_from_the_game() {
+        let mut game = game(GameConfig {
+            board_size: Some((6, 5)),
+            fog_radius: Some(1),
+            ..Default::default()
+        });
+        // Same sealed pocket as without fog, the walls of it lying outside the fog radius
+        game.snake_position = vec![(0, 2), (1, 2), (2, 2), (2, 1), (2, 0), (3, 0)];
+        game.snake_velocity = (-1, 0);
+        assert_eq!(game.reachable_free_cells((5, 4)), 21);
+    }
+
+    fn recorded_replay(seed: u64, steps: usize) -> Replay {
+        let mut game = seeded(seed);
+        game.recording = Some(Replay::new(10));
+        game.run_headless_with(GreedyAgent::default(), steps);
+        game.recording.unwrap()
+    }
+
+    #[test]
+    fn altered_outcome_is_reported_as_divergence() {
+        let mut replay = recorded_replay(6, 40);
+        assert!(seeded(6).validate_replay(&replay).is_ok());
+
+        let (score, length) = replay.outcome.unwrap();
+        replay.outcome = Some((score + 1, length));
+        let divergence = seeded(6).validate_replay(&replay).unwrap_err();
+        assert_eq!(divergence.step, replay.steps.len());
+        assert!(divergence.reason.contains("expected score"));
+    }
+
+    #[test]
+    fn axis_labels_sit_in_opposite_corners() {
+        // Columns along the top edge, rows along the left one, 31 pixel cells
+        assert_eq!(axis_label_position(0, true), (2, 2));
+        assert_eq!(axis_label_position(3, true), (95, 2));
+        assert_eq!(axis_label_position(0, false), (2, 19));
+        assert_eq!(axis_label_position(3, false), (2, 112));
+    }
+
+    #[test]
+    fn lives_respawn_in_the_middle_until_they_run_out() {
+        let mut game = game(GameConfig {
+            respawn_lives: 1,
+            start_length: 3,
+            ..Default::default()
+        });
+        game.fruit_position = Some((3, 0));
+        game.step(Move::FW);
+        assert_eq!((game.score, game.snake_position.len()), (1, 4));
+
+        game.step(Move::LT);
+        assert!(!game.game_over);
+        assert_eq!(game.lives, 0);
+        assert_eq!(game.score, 1);
+        assert_eq!(game.snake_position.len(), 1);
+        let (x, y) = game.snake_position[0];
+        assert!((7..=8).contains(&x) && (7..=8).contains(&y));
+
+        // Still heading up, straight into the top wall with no lives left
+        for _ in 0..10 {
+            game.fruit_position = Some((15, 15));
+            game.step(Move::FW);
+        }
+        assert!(game.game_over);
+        assert_eq!(game.score, 1);
+    }
+
+    #[test]
+    fn profile_shares_add_up_to_the_whole() {
+        let mut game = seeded(2);
+        game.profile = Some(Vec::new());
+        game.run_headless_with(GreedyAgent::default(), 50);
+        let shares = game.profile_shares().unwrap();
+        let names: Vec<_> = shares.iter().map(|&(name, _)| name).collect();
+        for section in ["observe", "decide", "update_snake", "update_env"] {
+            assert!(names.contains(&section), "{} missing", section);
+        }
+        let total: f64 = shares.iter().map(|&(_, share)| share).sum();
+        assert!((total - 100.0).abs() < 1e-9, "total {}", total);
+    }
+
+    fn bouncing_game(snake: Vec<(isize, isize)>, velocity: (isize, isize)) -> GameState<'static> {
+        let mut game = game(GameConfig {
+            walls: WallBehavior::Bounce,
+            board_size: Some((8, 8)),
+            ..Default::default()
+        });
+        game.snake_position = snake;
+        game.snake_velocity = velocity;
+        game.fruit_position = Some((4, 4));
+        game
+    }
+
+    #[test]
+    fn bounce_reflects_off_every_edge() {
+        let cases = [
+            (vec![(7, 2), (6, 2), (5, 2)], (1, 0)),
+            (vec![(0, 2), (1, 2), (2, 2)], (-1, 0)),
+            (vec![(2, 7), (2, 6), (2, 5)], (0, 1)),
+            (vec![(2, 0), (2, 1), (2, 2)], (0, -1)),
+        ];
+        for (snake, velocity) in cases {
+            let mut game = bouncing_game(snake.clone(), velocity);
+            game.step(Move::FW);
+            let reflected = (-velocity.0, -velocity.1);
+            assert_eq!(game.snake_velocity, reflected);
+            let tail = snake[2];
+            assert_eq!(
+                game.snake_position,
+                vec![
+                    (tail.0 + reflected.0, tail.1 + reflected.1),
+                    snake[2],
+                    snake[1]
+                ]
+            );
+            assert!(!game.game_over);
+        }
+    }
+
+    #[test]
+    fn bounce_follows_a_bent_tail() {
+        // Heading straight back from the tail would run into the bend of the U
+        let snake = vec![(7, 2), (6, 2), (5, 2), (5, 3), (6, 3)];
+        let mut game = bouncing_game(snake, (1, 0));
+        game.step(Move::FW);
+        assert_eq!(game.snake_velocity, (1, 0));
+        assert_eq!(game.snake_position[0], (7, 3));
+        assert!(!game.game_over);
+    }
+
+    fn next_fruit(game: &mut GameState, count: usize) -> Vec<(isize, isize)> {
+        (0..count)
+            .map(|_| {
+                game.fruit_position = None;
+                game.spawn_fruit((0, 0));
+                game.fruit_position.unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn saved_rng_state_continues_the_fruit_sequence() {
+        for rng in [RngBackend::Std(12), RngBackend::Counter(12)] {
+            let config = GameConfig {
+                rng,
+                ..Default::default()
+            };
+            let mut original = game(config.clone());
+            next_fruit(&mut original, 7);
+            let path = temp_path("rng.save");
+            save_game(&path, &original.snapshot()).unwrap();
+            let expected = next_fruit(&mut original, 10);
+
+            // A fresh generator from the same seed picks up where the save left off
+            let mut resumed = game(config);
+            resumed.restore(&load_game(&path).unwrap());
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(next_fruit(&mut resumed, 10), expected);
+        }
+    }
+
+    #[test]
+    fn agent_takes_over_a_fatal_keyboard_step() {
+        let mut game = game(GameConfig {
+            coop_takeover: true,
+            start_length: 3,
+            ..Default::default()
+        });
+        game.score = 5;
+        game.snake_velocity = (0, -1);
+        game.fruit_position = Some((15, 15));
+        let before = game.snake_position.clone();
+        let mut allow_move = true;
+        game.keyboard_frame(0, None, KeyboardKey::KEY_N, false, &mut allow_move);
+
+        // The step into the wall is undone and nothing was reset
+        assert!(!game.game_over);
+        assert!(game.rescuing && matches!(game.control_mode, Mode::Agent(_)));
+        assert_eq!(game.snake_position, before);
+        assert_eq!(game.score, 5);
+
+        // The only way out is right, where the agent finds a fruit and hands back
+        game.fruit_position = Some((3, 0));
+        game.step_agent();
+        assert_eq!(game.snake_position[0], (3, 0));
+        assert_eq!(game.score, 6);
+        assert!(!game.rescuing && matches!(game.control_mode, Mode::Keyboard));
+    }
+
+    #[test]
+    fn occupied_cells_match_the_dense_grid() {
+        let mut game = seeded(9);
+        game.run_headless_with(GreedyAgent::default(), 60);
+        game.shrink_powerups = vec![(15, 0)];
+        let occupied = game.occupied_cells();
+
+        let mut dense = Vec::new();
+        for (y, row) in game.observe_board().grid().iter().enumerate() {
+            for (x, &cell) in row.iter().enumerate() {
+                let kind = match cell {
+                    agent::HEAD => CellKind::Head,
+                    agent::BODY => CellKind::Body,
+                    agent::FRUIT => CellKind::Fruit,
+                    _ => continue,
+                };
+                dense.push((x as isize, y as isize, kind));
+            }
+        }
+        let mut sparse: Vec<_> = occupied
+            .iter()
+            .copied()
+            .filter(|&(_, _, kind)| kind != CellKind::ShrinkPowerup)
+            .collect();
+        let order = |&(x, y, _): &(isize, isize, CellKind)| (y, x);
+        sparse.sort_by_key(order);
+        assert!(sparse == dense);
+        assert!(occupied.contains(&(15, 0, CellKind::ShrinkPowerup)));
+        // The head comes last, so it's drawn on top
+        assert!(
+            occupied.last()
+                == Some(&(
+                    game.snake_position[0].0,
+                    game.snake_position[0].1,
+                    CellKind::Head
+                ))
+        );
+    }
+
+    // Reward an external controller gets for a step that fills the top row of a 4x4 board
+    fn row_bonus_reward(reward_clip: Option<isize>) -> ((isize, f64), isize) {
+        let mut game = game(GameConfig {
+            board_size: Some((4, 4)),
+            line_bonus: 50,
+            reward_clip,
+            ..Default::default()
+        });
+        game.snake_position = vec![(2, 0), (1, 0), (0, 0)];
+        game.pending_growth = 1;
+        game.fruit_position = Some((3, 3));
+        let (move_tx, moves) = std::sync::mpsc::channel();
+        let (reward, reward_rx) = std::sync::mpsc::channel();
+        game.control_mode = Mode::External { moves, reward };
+        move_tx.send(Move::FW).unwrap();
+        game.step_agent();
+        (reward_rx.try_recv().unwrap(), game.score)
+    }
+
+    #[test]
+    fn rewards_are_clipped_only_when_asked() {
+        assert_eq!(row_bonus_reward(Some(10)), ((10, 10.0), 50));
+        assert_eq!(row_bonus_reward(None), ((50, 50.0), 50));
+    }
+
+    #[test]
+    fn debug_string_lays_out_the_board() {
+        let mut mask = vec![true; 15];
+        mask[2 * 5 + 4] = false;
+        let mut game = game(GameConfig {
+            board_size: Some((5, 3)),
+            playable_mask: Some(mask),
+            ..Default::default()
+        });
+        game.snake_position = vec![(2, 1), (1, 1)];
+        game.fruit_position = Some((4, 0));
+        game.shrink_powerups = vec![(0, 2)];
+        let expected = "\
+#######
+#....*#
+#.o@..#
+#-...##
+#######
+";
+        assert_eq!(game.to_debug_string(), expected);
+    }
+
+    #[test]
+    fn paused_start_waits_for_the_start_signal() {
+        let mut game = game(GameConfig {
+            start_length: 2,
+            ..Default::default()
+        });
+        game.fruit_position = Some((15, 15));
+        let start = game.snapshot();
+        let (mut frames_counter, mut allow_move) = (0, true);
+        let mut play = |game: &mut GameState, paused| {
+            for _ in 0..30 {
+                if let Some(frame) = next_frame(paused, &mut frames_counter) {
+                    game.keyboard_frame(frame, None, KeyboardKey::KEY_N, false, &mut allow_move);
+                }
+            }
+        };
+
+        play(&mut game, true);
+        assert!(game.snapshot() == start);
+        // Let go, the first frame played moves the snake right away
+        play(&mut game, false);
+        assert_eq!(game.steps_taken, 3);
+        assert_eq!(game.snake_position[0], (4, 0));
+    }
+
+    #[test]
+    fn eat_frame_draws_the_head_over_the_fruit() {
+        let mut eaten = game(GameConfig {
+            start_length: 2,
+            ..Default::default()
+        });
+        eaten.fruit_position = Some((2, 0));
+        eaten.step(Move::FW);
+        let head = eaten.snake_position[0];
+        assert_eq!(head, (2, 0));
+        assert_ne!(eaten.fruit_position, Some(head));
+        assert_eq!(
+            eaten
+                .to_debug_string()
+                .lines()
+                .nth(1)
+                .unwrap()
+                .chars()
+                .nth(3),
+            Some('@')
+        );
+
+        // A fruit left under the head waiting for the eat action still hides under it
+        let mut waiting = game(GameConfig {
+            start_length: 2,
+            eat_requires_action: true,
+            ..Default::default()
+        });
+        waiting.fruit_position = Some((2, 0));
+        waiting.step(Move::FW);
+        assert_eq!(waiting.fruit_position, Some((2, 0)));
+        // Passing over it without the eat action neither grows the snake nor scores
+        assert_eq!((waiting.snake_position.len(), waiting.score), (2, 0));
+        assert!(!waiting.to_debug_string().contains('*'));
+    }
+
+    #[test]
+    fn score_cap_ends_the_game_as_solved() {
+        let mut capped = game(GameConfig {
+            start_length: 2,
+            score_cap: Some(FRUIT_REWARD),
+            ..Default::default()
+        });
+        capped.fruit_position = Some((3, 0));
+        capped.step(Move::FW);
+        assert!(!capped.game_over);
+        capped.step(Move::FW);
+        assert!(capped.game_over);
+        assert!(capped.solved);
+        assert_eq!(capped.score, FRUIT_REWARD);
+    }
+
+    fn plus_mask() -> Vec<bool> {
+        // .#.
+        // ###
+        // .#.
+        vec![false, true, false, true, true, true, false, true, false]
+    }
+
+    #[test]
+    fn mask_blocks_movement_outside_the_shape() {
+        let mut plus = game(GameConfig {
+            board_size: Some((3, 3)),
+            playable_mask: Some(plus_mask()),
+            ..Default::default()
+        });
+        // The snake starts on the first playable cell rather than in the cut-out corner
+        assert_eq!(plus.snake_position, vec![(1, 0)]);
+        // and heads down, since right runs straight into the mask
+        assert_eq!(plus.snake_velocity, (0, 1));
+        plus.fruit_position = None;
+        plus.step(Move::FW);
+        assert_eq!(plus.snake_position[0], (1, 1));
+        assert!(!plus.game_over);
+        plus.step(Move::RT);
+        assert_eq!(plus.snake_position[0], (0, 1));
+        assert!(!plus.game_over);
+        // A second right turn heads north into the cut-out corner
+        plus.step(Move::RT);
+        assert!(plus.game_over);
+    }
+
+    #[test]
+    fn mask_that_no_longer_fits_the_board_is_dropped() {
+        let mut config = GameConfig {
+            board_size: Some((3, 3)),
+            playable_mask: Some(plus_mask()),
+            ..Default::default()
+        };
+        drop_misfit_mask(&mut config);
+        assert!(config.playable_mask.is_some());
+        config.board_size = Some((20, 20));
+        drop_misfit_mask(&mut config);
+        assert_eq!(config.playable_mask, None);
+    }
+
+    #[test]
+    fn center_text_subtracts_half_the_text_size() {
+        // A wide board, the text centered on both axes rather than pushed left or to the top
+        assert_eq!(center_text(640, 320, 200, 40), (220, 140));
+        assert_eq!(center_text(320, 640, 200, 40), (60, 300));
+        assert_eq!(centered_text(100, 50, 30, 10, 20), (110, 55));
+    }
+
+    #[test]
+    fn seeded_random_start_direction_is_reproducible_and_safe() {
+        let start = |seed| {
+            game(GameConfig {
+                rng: RngBackend::Std(seed),
+                random_start_direction: true,
+                start_length: 1,
+                start_cell: Some((0, 0)),
+                ..Default::default()
+            })
+        };
+        let mut headings = std::collections::HashSet::new();
+        for seed in 0..50 {
+            let first = start(seed);
+            assert_eq!(first.snake_velocity, start(seed).snake_velocity);
+            // In the corner only right and down stay on the board
+            assert!(first.is_playable(first.ahead_of(first.snake_position[0])));
+            headings.insert(first.snake_velocity);
+        }
+        assert_eq!(headings.len(), 2);
+    }
+
+    #[test]
+    fn fit_board_scales_and_centers_on_resize() {
+        // A 10x10 board takes 310x350 pixels with the HUD
+        let (offset, zoom) = fit_board((310, 350), (10, 10));
+        assert_eq!((offset.x, offset.y, zoom), (0.0, 0.0, 1.0));
+        // Width is the tighter fit, so the board is centered vertically
+        let (offset, zoom) = fit_board((620, 1000), (10, 10));
+        assert_eq!((offset.x, offset.y, zoom), (0.0, 150.0, 2.0));
+        let (offset, zoom) = fit_board((1000, 350), (10, 10));
+        assert_eq!((offset.x, offset.y, zoom), (345.0, 0.0, 1.0));
+        // A minimised window keeps the default layout
+        let (offset, zoom) = fit_board((0, 0), (10, 10));
+        assert_eq!((offset.x, offset.y, zoom), (0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn wrapped_head_collides_only_with_a_real_overlap() {
+        // The head comes back in on the left onto a segment that stays put
+        let mut overlap = wrapping_game();
+        overlap.snake_position = vec![(4, 2), (4, 3), (0, 3), (0, 2), (0, 1)];
+        overlap.step(Move::FW);
+        assert_eq!(overlap.snake_position[0], (0, 2));
+        assert!(overlap.game_over);
+
+        // Straddling the edge, and coming back in onto the cell the tail just left
+        let mut straddle = wrapping_game();
+        straddle.snake_position = vec![(1, 2), (0, 2), (4, 2), (3, 2)];
+        straddle.step(Move::FW);
+        assert!(!straddle.game_over);
+        let mut chasing = wrapping_game();
+        chasing.snake_position = vec![(4, 2), (4, 3), (0, 3), (0, 2)];
+        chasing.step(Move::FW);
+        assert_eq!(chasing.snake_position[0], (0, 2));
+        assert!(!chasing.game_over);
+    }
+
+    fn graced_game(grace: usize) -> GameState<'static> {
+        let mut graced = game(GameConfig {
+            board_size: Some((5, 5)),
+            spawn_grace_steps: grace,
+            start_length: 2,
+            ..Default::default()
+        });
+        graced.fruit_position = Some((0, 4));
+        graced.snake_position = vec![(4, 0), (3, 0)];
+        graced
+    }
+
+    #[test]
+    fn grace_period_forgives_only_early_collisions() {
+        // The first step runs into the right wall and comes back in on the left
+        let mut early = graced_game(1);
+        early.step(Move::FW);
+        assert!(!early.game_over);
+        assert_eq!(early.snake_position, vec![(0, 0), (4, 0)]);
+        early.check_invariants();
+
+        let mut late = graced_game(0);
+        late.step(Move::FW);
+        assert!(late.game_over);
+    }
+
+    #[test]
+    fn grace_period_never_leaves_the_head_in_the_body_or_a_wall() {
+        // Wrapping would land on the body, so the snake turns down instead
+        let mut coiled = graced_game(5);
+        coiled.snake_position = vec![(4, 0), (3, 0), (2, 0), (1, 0), (0, 0)];
+        coiled.pending_growth = 1;
+        coiled.step(Move::FW);
+        assert_eq!(coiled.snake_position[0], (4, 1));
+        assert_eq!(coiled.snake_velocity, (0, 1));
+        coiled.check_invariants();
+
+        // Wrapping would land in a masked cell
+        let mut mask = vec![true; 25];
+        mask[0] = false;
+        let mut masked = graced_game(5);
+        masked.config.playable_mask = Some(mask);
+        masked.step(Move::FW);
+        assert!(!masked.game_over);
+        assert_eq!(masked.snake_position[0], (4, 1));
+        masked.check_invariants();
+    }
+
+    #[test]
+    fn previews_are_drawn_every_nth_step_only() {
+        let drawn: Vec<_> = (0..10).filter(|&step| should_preview(step, 4)).collect();
+        assert_eq!(drawn, vec![0, 4, 8]);
+        // Zero turns previews off rather than dividing by it
+        assert!((0..10).all(|step| !should_preview(step, 0)));
+    }
+
+    #[test]
+    fn noop_key_is_logged_apart_from_no_input() {
+        let path = temp_path("noop.input");
+        let log = [(3, KeyboardKey::KEY_DOWN), (12, KeyboardKey::KEY_N)];
+        save_input_log(&path, &log, KeyboardKey::KEY_N).unwrap();
+        // Frames with no key press leave no line at all
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "3 DOWN\n12 NOOP\n");
+        // Rebinding the key doesn't change what the log means
+        let loaded = load_input_log(&path, KeyboardKey::KEY_SPACE).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            Vec::from(loaded),
+            vec![(3, KeyboardKey::KEY_DOWN), (12, KeyboardKey::KEY_SPACE)]
+        );
+    }
+
+    #[test]
+    fn decision_log_has_one_record_per_step() {
+        let mut logged = seeded(3);
+        logged.decision_log = Some(Vec::new());
+        logged.run_headless_with(GreedyAgent::default(), 6);
+        assert_eq!(logged.steps_taken, 6);
+        let log = logged.decision_log.unwrap();
+        assert_eq!(log.len(), 6);
+        for (step, record) in log.iter().enumerate() {
+            assert!(!record.contains('\n'));
+            assert!(record.starts_with(&format!("{{\"step\":{},\"head\":[", step)));
+            assert!(record.contains("\"velocity\":["));
+            assert!(record.contains("\"length\":"));
+            assert!(record.contains("\"move\":\""));
+            assert!(record.ends_with("\"blocked\":false}"));
+        }
+    }
+
+    #[test]
+    fn wrap_event_exposes_the_entry_cell() {
+        let entries = |wrapped: &GameState| -> Vec<_> {
+            wrapped
+                .events
+                .iter()
+                .filter_map(|event| match event {
+                    GameEvent::Wrapped(cell) => Some(*cell),
+                    _ => None,
+                })
+                .collect()
+        };
+        let mut right = wrapping_game();
+        right.snake_position = vec![(3, 2), (2, 2)];
+        right.step(Move::FW);
+        assert_eq!(entries(&right), vec![]);
+        right.step(Move::FW);
+        assert_eq!(entries(&right), vec![(0, 2)]);
+
+        // Going up off the top comes back in on the bottom row
+        let mut up = wrapping_game();
+        up.snake_position = vec![(1, 0), (1, 1)];
+        up.snake_velocity = (0, -1);
+        up.step(Move::FW);
+        assert_eq!(entries(&up), vec![(1, 4)]);
+    }
+
+    #[test]
+    fn level_pixels_decode_into_the_layout() {
+        let (wall, floor) = (Color::new(0, 0, 0, 255), Color::new(255, 255, 255, 255));
+        let (start, fruit) = (Color::new(0, 0, 255, 255), Color::new(0, 255, 0, 255));
+        // #S.
+        // .#F
+        let pixels = [wall, start, floor, floor, wall, fruit];
+        let level = decode_level(3, &pixels).unwrap();
+        assert_eq!(level.board_size, (3, 2));
+        assert_eq!(level.mask, vec![false, true, true, true, false, true]);
+        assert_eq!(level.start, (1, 0));
+        assert_eq!(level.fruit, Some((2, 1)));
+
+        assert!(decode_level(3, &[floor; 6]).is_err());
+        assert!(decode_level(3, &[start, floor, start, floor, floor, floor]).is_err());
+    }
+
+    #[test]
+    fn level_start_keeps_the_body_and_heading_out_of_walls() {
+        // ..#S#
+        // .....
+        let mut mask = vec![true; 10];
+        mask[2] = false;
+        mask[4] = false;
+        let level = game(GameConfig {
+            board_size: Some((5, 2)),
+            playable_mask: Some(mask),
+            start_cell: Some((3, 0)),
+            start_length: 3,
+            ..Default::default()
+        });
+        assert_eq!(level.snake_position, vec![(3, 0)]);
+        assert_eq!(level.snake_velocity, (0, 1));
+    }
+
+    #[test]
+    fn level_start_off_a_resized_board_is_dropped() {
+        let mut config = GameConfig {
+            board_size: Some((4, 4)),
+            start_cell: Some((6, 1)),
+            start_fruit: Some((2, 2)),
+            ..Default::default()
+        };
+        drop_misfit_mask(&mut config);
+        assert_eq!(
+            (config.start_cell, config.start_fruit),
+            (None, Some((2, 2)))
+        );
+    }
+
+    #[test]
+    fn agents_eat_when_eating_takes_an_action() {
+        let agents: [Box<dyn Agent>; 2] =
+            [Box::new(GreedyAgent::default()), Box::new(FloodFillAgent)];
+        for agent in agents {
+            let mut puzzle = game(GameConfig {
+                rng: RngBackend::Std(4),
+                eat_requires_action: true,
+                ..Default::default()
+            });
+            puzzle.control_mode = Mode::Agent(agent);
+            puzzle.run_headless(200);
+            assert!(puzzle.fruits_eaten >= 3, "ate {}", puzzle.fruits_eaten);
+        }
+    }
+
+    #[test]
+    fn held_eat_key_is_logged_and_replayed() {
+        let path = temp_path("eat.input");
+        let log = [
+            (10, KeyboardKey::KEY_E),
+            (10, KeyboardKey::KEY_DOWN),
+            (11, KeyboardKey::KEY_E),
+        ];
+        save_input_log(&path, &log, KeyboardKey::KEY_N).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "10 EAT\n10 DOWN\n11 EAT\n"
+        );
+        let mut events = load_input_log(&path, KeyboardKey::KEY_N).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let replayed: Vec<_> = (9..13)
+            .map(|frame| {
+                let eat = next_logged_eat(&mut events, frame);
+                (eat, next_logged_key(&mut events, frame))
+            })
+            .collect();
+        assert_eq!(
+            replayed,
+            vec![
+                (false, None),
+                (true, Some(KeyboardKey::KEY_DOWN)),
+                (true, None),
+                (false, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn autosave_is_due_after_the_interval_and_once_at_game_over() {
+        assert!(!autosave_due(29.0, 30.0, false, false, false));
+        assert!(autosave_due(30.5, 30.0, false, false, false));
+        assert!(!autosave_due(30.5, 30.0, true, false, false));
+        // The end of the game is saved straight away, and only once
+        assert!(autosave_due(1.0, 30.0, false, true, false));
+        assert!(!autosave_due(60.0, 30.0, false, true, true));
+    }
+
+    #[test]
+    fn autosave_file_loads_back_into_the_game() {
+        let path = temp_path("autosave.save");
+        let mut played = scoring_game();
+        played.run_headless_with(GreedyAgent::default(), 30);
+        save_game(&path, &played.snapshot()).unwrap();
+        let saved = load_game(&path).unwrap();
+        assert!(saved == played.snapshot());
+        let mut resumed = scoring_game();
+        resumed.restore(&saved);
+        assert!(resumed.snapshot() == played.snapshot());
+
+        // A lost game is saved as over, which the next launch doesn't offer to resume
+        played.game_over = true;
+        save_game(&path, &played.snapshot()).unwrap();
+        assert!(load_game(&path).unwrap().game_over);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn daily_seed_gives_everyone_the_same_board() {
+        let daily = || RngBackend::Std(daily::seed_for(20_741));
+        assert!(fruit_sequence(daily()) == fruit_sequence(daily()));
+        let tomorrow = RngBackend::Std(daily::seed_for(20_742));
+        assert!(fruit_sequence(daily()) != fruit_sequence(tomorrow));
+    }
+
+    #[test]
+    fn delayed_agent_sees_the_state_from_steps_before() {
+        let mut lagging = game(GameConfig {
+            observation_delay: 2,
+            start_length: 1,
+            ..Default::default()
+        });
+        lagging.fruit_position = Some((15, 15));
+        let seen = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let log = seen.clone();
+        lagging.run_headless_with(
+            move |obs: &Observation| {
+                log.borrow_mut().push(obs.snake[0]);
+                Move::FW
+            },
+            6,
+        );
+        // The first state stands in until two steps have gone by
+        let heads: Vec<_> = [0, 0, 0, 1, 2, 3].map(|x| (x, 0)).into();
+        assert_eq!(*seen.borrow(), heads);
+    }
+
+    #[test]
+    fn first_visits_pay_the_decayed_bonus_and_revisits_nothing() {
+        let mut exploring = game(GameConfig {
+            exploration_bonus: 100,
+            exploration_half_life: 2,
+            start_length: 1,
+            ..Default::default()
+        });
+        exploring.fruit_position = Some((15, 15));
+        let paid: Vec<_> = [Move::FW, Move::RT, Move::RT, Move::RT]
+            .into_iter()
+            .map(|mv| {
+                let before = exploring.exploration_score;
+                exploring.step(mv);
+                exploring.exploration_score - before
+            })
+            .collect();
+        // 100 halving every two steps, then back on the start cell
+        assert_eq!(paid, vec![71, 50, 35, 0]);
+        assert_eq!(exploring.score, 156);
+    }
+
+    #[test]
+    fn normalized_constant_rewards_trend_to_zero() {
+        let mut stats = RunningStats::default();
+        stats.push(10.0);
+        stats.push(-10.0);
+        let mut normalized = Vec::new();
+        for n in 1..=1000 {
+            stats.push(1.0);
+            if [10, 100, 1000].contains(&n) {
+                normalized.push(stats.normalize(1.0).abs());
+            }
+        }
+        assert!(normalized.windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(normalized[2] < 0.05);
+    }
+
+    #[test]
+    fn fruit_trapped_behind_the_body_moves_to_a_free_reachable_cell() {
+        for seed in 0..100 {
+            let mut trapped = game(GameConfig {
+                rng: RngBackend::Std(seed),
+                board_size: Some((5, 5)),
+                relocate_unreachable_fruit: true,
+                ..Default::default()
+            });
+            // The body cuts the board in two, the tail on the head's side
+            trapped.snake_position = vec![(4, 3), (4, 2), (3, 2), (2, 2), (1, 2), (0, 2), (0, 3)];
+            trapped.fruit_position = Some((2, 0));
+            trapped.fruit_log = Some(Vec::new());
+            let spawned = trapped.fruits_spawned;
+            trapped.relocate_unreachable_fruit();
+            let (x, y) = trapped.fruit_position.unwrap();
+            assert!(y >= 3, "{:?}", (x, y));
+            assert!(!trapped.snake_position.contains(&(x, y)));
+            // The relocation is counted as a spawn along with its log entry
+            assert_eq!(trapped.fruits_spawned, spawned + 1);
+            assert_eq!(trapped.fruit_log, Some(vec![(x, y)]));
+        }
+    }
+
+    #[test]
+    fn tampered_replays_fail_digest_verification() {
+        let replay = recorded_replay(8, 40);
+        assert!(seeded(8).verify_digest(&replay));
+
+        let mut moved = recorded_replay(8, 40);
+        let (fruit, mv) = moved.steps[20];
+        moved.steps[20] = (
+            fruit,
+            if mv.name() == "LT" {
+                Move::RT
+            } else {
+                Move::LT
+            },
+        );
+        assert!(!seeded(8).verify_digest(&moved));
+
+        let mut claimed = recorded_replay(8, 40);
+        let (score, length) = claimed.outcome.unwrap();
+        claimed.outcome = Some((score + 10, length));
+        assert!(!seeded(8).verify_digest(&claimed));
+    }
+
+    fn lengths_while_aging(start_length: usize, every: usize, steps: usize) -> Vec<usize> {
+        let mut aging = game(GameConfig {
+            start_length,
+            tail_decay_interval: Some(every),
+            ..Default::default()
+        });
+        aging.fruit_position = Some((15, 15));
+        (0..steps)
+            .map(|_| {
+                aging.step(Move::FW);
+                aging.snake_position.len()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tail_shrinks_every_k_steps_without_eating() {
+        assert_eq!(
+            lengths_while_aging(5, 3, 10),
+            vec![5, 5, 4, 4, 4, 3, 3, 3, 2, 2]
+        );
+        // Never below the head
+        assert_eq!(lengths_while_aging(3, 1, 4), vec![2, 1, 1, 1]);
+    }
+
+    #[test]
+    fn capture_rate_counts_eaten_out_of_spawned_fruit() {
+        let mut counted = game(GameConfig {
+            start_length: 1,
+            start_fruit: Some((2, 0)),
+            ..Default::default()
+        });
+        assert_eq!(counted.fruit_capture_rate(), Some(0.0));
+        counted.step(Move::FW);
+        counted.step(Move::FW);
+        assert_eq!(counted.fruit_capture_rate(), Some(1.0));
+        // The next fruit spawns on the following step and is still there
+        counted.step(Move::FW);
+        assert_eq!((counted.fruits_eaten, counted.fruits_spawned), (1, 2));
+        assert_eq!(counted.fruit_capture_rate(), Some(0.5));
+
+        assert_eq!(game(GameConfig::default()).fruit_capture_rate(), None);
+    }
+
+    #[test]
+    fn practice_death_before_the_first_meal_rewinds_to_the_start() {
+        let mut practicing = game(GameConfig {
+            practice: Some(PracticeConfig { lives: 1 }),
+            start_length: 3,
+            ..Default::default()
+        });
+        practicing.fruit_position = Some((15, 15));
+        let start = practicing.snapshot();
+        practicing.step(Move::LT);
+        assert!(!practicing.game_over);
+        assert_eq!(practicing.lives, 0);
+        assert_eq!(practicing.snake_position, start.snake);
+    }
+
+    #[test]
+    fn fatal_head_growth_is_not_credited_as_a_meal() {
+        let mut practicing = game(GameConfig {
+            practice: Some(PracticeConfig { lives: 1 }),
+            growth_side: GrowthSide::Head,
+            start_length: 2,
+            ..Default::default()
+        });
+        // Eating on the last column grows the head off the board
+        practicing.snake_position = vec![(14, 0), (13, 0)];
+        practicing.fruit_position = Some((15, 0));
+        practicing.step(Move::FW);
+        assert!(!practicing.game_over);
+        assert_eq!(practicing.lives, 0);
+        assert_eq!((practicing.score, practicing.fruits_eaten), (0, 0));
+        assert!(!practicing
+            .events
+            .iter()
+            .any(|event| matches!(event, GameEvent::Ate)));
+    }
+
+    #[test]
+    fn starting_cells_pay_no_coverage_bonus() {
+        // 4x4 board, each cell worth 10 of the 160 bonus
+        let mut covering = game(GameConfig {
+            board_size: Some((4, 4)),
+            start_length: 3,
+            coverage_bonus: 160,
+            ..Default::default()
+        });
+        covering.fruit_position = Some((3, 3));
+        covering.step(Move::RT);
+        assert_eq!(covering.score, 10);
+        assert_eq!(covering.coverage_awarded, 40);
+    }
+
+    #[test]
+    fn each_step_consumes_exactly_one_external_move() {
+        let mut driven = seeded(2);
+        let (move_tx, moves) = std::sync::mpsc::channel();
+        let (reward, reward_rx) = std::sync::mpsc::channel();
+        driven.control_mode = Mode::External { moves, reward };
+        // Nothing sent yet, the windowed loop would keep drawing instead of blocking
+        assert!(!driven.external_move_ready());
+
+        for mv in [Move::FW, Move::RT, Move::FW, Move::RT, Move::FW] {
+            move_tx.send(mv).unwrap();
+        }
+        assert!(driven.external_move_ready());
+        for _ in 0..5 {
+            driven.step_agent();
+        }
+        assert_eq!(driven.steps_taken, 5);
+        assert_eq!(reward_rx.try_iter().count(), 5);
+        let Mode::External { moves, .. } = &driven.control_mode else {
+            unreachable!()
+        };
+        assert!(moves.try_recv().is_err());
+        assert!(!driven.external_move_ready());
+    }
+
+    #[test]
+    fn start_paused_is_refused_without_a_window() {
+        let paused = |headless, attach_after| {
+            let mut options = Options {
+                start_paused: true,
+                headless,
+                attach_after,
+                ..Default::default()
+            };
+            drop_headless_pause(&mut options);
+            options.start_paused
+        };
+        assert!(paused(None, None));
+        assert!(!paused(Some(100), None));
+        // The window attached partway through starts paused
+        assert!(paused(Some(100), Some(50)));
+    }
 }
@@ -0,0 +1,321 @@
+use crate::agent::Observation;
+use crate::Move;
+
+// Starting value of a replay digest, the FNV-1a offset basis
+pub const DIGEST_SEED: u64 = 0xcbf2_9ce4_8422_2325;
+
+// Full copy of the environment, enough to resume a game without replaying from the start
+#[derive(Default, Clone, PartialEq)]
+pub struct Snapshot {
+    pub snake: Vec<(isize, isize)>,
+    pub fruit: Option<(isize, isize)>,
+    pub velocity: (isize, isize),
+    pub pending_growth: usize,
+    pub game_over: bool,
+    // Position of the seeded random stream, so fruit after a restore comes out the same
+    pub rng_state: Option<u128>,
+    pub score: isize,
+    pub steps_taken: usize,
+    pub lives: usize,
+    pub coverage_awarded: isize,
+    pub exploration_score: isize,
+    // Rows then columns that already paid the line bonus, and cells visited as [y][x],
+    // both left empty by saves that predate them
+    pub credited_lines: (Vec<bool>, Vec<bool>),
+    pub visited: Vec<Vec<bool>>,
+    pub shrink_powerups: Vec<(isize, isize)>,
+}
+
+// Recorded run made of a keyframe every `interval` steps plus every step in between
+// Each step keeps the fruit the agent saw alongside its move, so playback
+// doesn't depend on the random number generator
+pub struct Replay {
+    pub interval: usize,
+    pub keyframes: Vec<(usize, Snapshot)>,
+    pub steps: Vec<(Option<(isize, isize)>, Move)>,
+    // Final score and snake length, kept so later rule changes can be caught
+    pub outcome: Option<(isize, usize)>,
+    // Rolling hash of every observation and move, None for recordings made without one
+    pub digest: Option<u64>,
+}
+
+// First point where a re-simulated replay stops matching the recording
+pub struct Divergence {
+    pub step: usize,
+    pub reason: String,
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "diverged at step {}: {}", self.step, self.reason)
+    }
+}
+
+impl Replay {
+    pub fn new(interval: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            keyframes: Vec::new(),
+            steps: Vec::new(),
+            outcome: None,
+            digest: Some(DIGEST_SEED),
+        }
+    }
+    // Records the move taken on a step, folding what the agent saw into the digest
+    pub fn push_step(&mut self, obs: &Observation, mv: Move) {
+        self.steps.push((obs.fruit, mv));
+        self.digest = self.digest.map(|digest| fold_digest(digest, obs, mv));
+    }
+    pub fn wants_keyframe(&self) -> bool {
+        self.steps.len().is_multiple_of(self.interval)
+    }
+    // Latest keyframe at or before the given step
+    pub fn keyframe_before(&self, step: usize) -> Option<&(usize, Snapshot)> {
+        self.keyframes.iter().rev().find(|(at, _)| *at <= step)
+    }
+
+    // Plain text, one line per keyframe or step:
+    //   interval <k>
+    //   key <step> <game_over> <pending_growth> <vx> <vy> <fruit> <x,y>...
+    //   <rest of the keyframe, see write_progress>
+    //   step <fruit> <move>
+    //   outcome <score> <length>
+    //   digest <hex>
+    // where a missing fruit is written as "-"
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let mut out = format!("interval {}\n", self.interval);
+        let mut keyframes = self.keyframes.iter().peekable();
+        for (i, (fruit, mv)) in self.steps.iter().enumerate() {
+            while let Some((_, snapshot)) = keyframes.next_if(|(at, _)| *at == i) {
+                out += &format!("key {} {}\n", i, write_snapshot(snapshot));
+                out += &write_progress(snapshot);
+            }
+            out += &format!("step {} {}\n", write_cell(*fruit), mv.name());
+        }
+        if let Some((score, length)) = self.outcome {
+            out += &format!("outcome {} {}\n", score, length);
+        }
+        if let Some(digest) = self.digest {
+            out += &format!("digest {:016x}\n", digest);
+        }
+        std::fs::write(path, out)
+    }
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut replay = Replay::new(1);
+        replay.digest = None;
+        for (number, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let parsed = match fields.as_slice() {
+                ["interval", k] => k.parse().map(|k| replay.interval = k).ok(),
+                ["key", at, snapshot @ ..] => (|| {
+                    replay
+                        .keyframes
+                        .push((at.parse().ok()?, read_snapshot(snapshot)?));
+                    Some(())
+                })(),
+                // Belongs to the keyframe right above it
+                [name @ ("rng" | "progress" | "lines" | "visited" | "powerups"), rest @ ..] => {
+                    (|| {
+                        let (_, snapshot) = replay.keyframes.last_mut()?;
+                        read_progress(name, rest, snapshot)
+                    })()
+                }
+                ["step", fruit, mv] => (|| {
+                    replay.steps.push((read_cell(fruit)?, Move::from_name(mv)?));
+                    Some(())
+                })(),
+                ["outcome", score, length] => (|| {
+                    replay.outcome = Some((score.parse().ok()?, length.parse().ok()?));
+                    Some(())
+                })(),
+                ["digest", hex] => u64::from_str_radix(hex, 16)
+                    .map(|digest| replay.digest = Some(digest))
+                    .ok(),
+                [] => Some(()),
+                _ => None,
+            };
+            if parsed.is_none() {
+                return Err(format!("Malformed replay line {}: {}", number + 1, line));
+            }
+        }
+        Ok(replay)
+    }
+}
+
+// FNV-1a over the step's board state and move as little-endian words, so the value
+// doesn't depend on the platform or the compiler's default hasher
+pub fn fold_digest(digest: u64, obs: &Observation, mv: Move) -> u64 {
+    let mut words = vec![
+        obs.board_size.0 as i64,
+        obs.board_size.1 as i64,
+        obs.velocity.0 as i64,
+        obs.velocity.1 as i64,
+        obs.wrap as i64,
+    ];
+    // A missing fruit is hashed as a cell that can't exist
+    let (fx, fy) = obs.fruit.unwrap_or((-1, -1));
+    words.extend([fx as i64, fy as i64, obs.snake.len() as i64]);
+    words.extend(obs.snake.iter().flat_map(|&(x, y)| [x as i64, y as i64]));
+    let bytes = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .chain(mv.name().bytes());
+    bytes.fold(digest, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+// Game in progress saved on its own, for resuming after the program was closed:
+//   snapshot <game_over> <pending_growth> <vx> <vy> <fruit> <x,y>...
+//   <rest of the snapshot, see write_progress>
+pub fn save_game(path: &str, snapshot: &Snapshot) -> std::io::Result<()> {
+    let out = format!(
+        "snapshot {}\n{}",
+        write_snapshot(snapshot),
+        write_progress(snapshot)
+    );
+    std::fs::write(path, out)
+}
+
+pub fn load_game(path: &str) -> Result<Snapshot, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut snapshot = None;
+    for (number, line) in text.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let parsed = match fields.as_slice() {
+            ["snapshot", rest @ ..] => read_snapshot(rest).map(|s| snapshot = Some(s)),
+            [name, rest @ ..] => snapshot
+                .as_mut()
+                .and_then(|snapshot| read_progress(name, rest, snapshot)),
+            [] => Some(()),
+        };
+        if parsed.is_none() {
+            return Err(format!("Malformed save line {}: {}", number + 1, line));
+        }
+    }
+    snapshot.ok_or("Incomplete save".to_string())
+}
+
+// Everything of a snapshot but its random stream position, as
+// "<game_over> <pending_growth> <vx> <vy> <fruit> <x,y>..."
+fn write_snapshot(snapshot: &Snapshot) -> String {
+    format!(
+        "{} {} {} {} {} {}",
+        snapshot.game_over as u8,
+        snapshot.pending_growth,
+        snapshot.velocity.0,
+        snapshot.velocity.1,
+        write_cell(snapshot.fruit),
+        snapshot
+            .snake
+            .iter()
+            .map(|&cell| write_cell(Some(cell)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+}
+
+fn read_snapshot(fields: &[&str]) -> Option<Snapshot> {
+    let [over, pending, vx, vy, fruit, snake @ ..] = fields else {
+        return None;
+    };
+    Some(Snapshot {
+        snake: snake
+            .iter()
+            .map(|cell| read_cell(cell).flatten())
+            .collect::<Option<Vec<_>>>()?,
+        fruit: read_cell(fruit)?,
+        velocity: (vx.parse().ok()?, vy.parse().ok()?),
+        pending_growth: pending.parse().ok()?,
+        game_over: *over == "1",
+        ..Default::default()
+    })
+}
+
+// Lines following a snapshot with the rest of its state:
+//   rng <state>
+//   progress <score> <steps_taken> <lives> <coverage_awarded> <exploration_score>
+//   lines <credited rows as 0/1> <credited columns as 0/1>
+//   visited <row as 0/1>...
+//   powerups <x,y>...
+// where rng is left out for generators that can't report their state
+fn write_progress(snapshot: &Snapshot) -> String {
+    let flags =
+        |flags: &[bool]| -> String { flags.iter().map(|&f| if f { '1' } else { '0' }).collect() };
+    let mut out = String::new();
+    if let Some(state) = snapshot.rng_state {
+        out += &format!("rng {}\n", state);
+    }
+    out += &format!(
+        "progress {} {} {} {} {}\n",
+        snapshot.score,
+        snapshot.steps_taken,
+        snapshot.lives,
+        snapshot.coverage_awarded,
+        snapshot.exploration_score
+    );
+    let (rows, columns) = &snapshot.credited_lines;
+    out += &format!("lines {} {}\n", flags(rows), flags(columns));
+    let visited: Vec<String> = snapshot.visited.iter().map(|row| flags(row)).collect();
+    out += &format!("visited {}\n", visited.join(" "));
+    let powerups: Vec<String> = snapshot
+        .shrink_powerups
+        .iter()
+        .map(|&cell| write_cell(Some(cell)))
+        .collect();
+    out += &format!("powerups {}\n", powerups.join(" "));
+    out
+}
+
+// Fills in the snapshot from one of the lines written by write_progress, None when the
+// line is malformed or isn't one of them
+fn read_progress(name: &str, fields: &[&str], snapshot: &mut Snapshot) -> Option<()> {
+    let flags = |text: &str| -> Option<Vec<bool>> {
+        text.chars()
+            .map(|c| match c {
+                '0' => Some(false),
+                '1' => Some(true),
+                _ => None,
+            })
+            .collect()
+    };
+    match (name, fields) {
+        ("rng", [state]) => snapshot.rng_state = Some(state.parse().ok()?),
+        ("progress", [score, steps, lives, coverage, exploration]) => {
+            snapshot.score = score.parse().ok()?;
+            snapshot.steps_taken = steps.parse().ok()?;
+            snapshot.lives = lives.parse().ok()?;
+            snapshot.coverage_awarded = coverage.parse().ok()?;
+            snapshot.exploration_score = exploration.parse().ok()?;
+        }
+        ("lines", [rows, columns]) => snapshot.credited_lines = (flags(rows)?, flags(columns)?),
+        ("visited", rows) => {
+            snapshot.visited = rows.iter().map(|row| flags(row)).collect::<Option<_>>()?
+        }
+        ("powerups", cells) => {
+            snapshot.shrink_powerups = cells
+                .iter()
+                .map(|cell| read_cell(cell).flatten())
+                .collect::<Option<_>>()?
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+fn write_cell(cell: Option<(isize, isize)>) -> String {
+    match cell {
+        Some((x, y)) => format!("{},{}", x, y),
+        None => "-".to_string(),
+    }
+}
+
+// Outer None means the field is malformed, inner None is an explicit "-"
+fn read_cell(field: &str) -> Option<Option<(isize, isize)>> {
+    if field == "-" {
+        return Some(None);
+    }
+    let (x, y) = field.split_once(',')?;
+    Some(Some((x.parse().ok()?, y.parse().ok()?)))
+}
@@ -0,0 +1,112 @@
+use rand::rngs::{SmallRng, ThreadRng};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+// Source of randomness for the environment
+#[derive(Default, Clone, Copy)]
+pub enum RngBackend {
+    // Fresh entropy every run, the original behaviour
+    #[default]
+    Thread,
+    Std(u64),
+    Small(u64),
+    // SplitMix64 over a plain counter, simple enough to port to other tools
+    Counter(u64),
+}
+
+impl RngBackend {
+    pub fn from_name(name: &str, seed: u64) -> Option<Self> {
+        match name {
+            "thread" => Some(RngBackend::Thread),
+            "std" => Some(RngBackend::Std(seed)),
+            "small" => Some(RngBackend::Small(seed)),
+            "counter" => Some(RngBackend::Counter(seed)),
+            _ => None,
+        }
+    }
+    pub fn build(&self) -> GameRng {
+        match *self {
+            RngBackend::Thread => GameRng::Thread(rand::rng()),
+            // Same generator and stream as StdRng, but able to report its position
+            RngBackend::Std(seed) => GameRng::Std(Box::new(ChaCha12Rng::seed_from_u64(seed))),
+            RngBackend::Small(seed) => GameRng::Small(SmallRng::seed_from_u64(seed)),
+            RngBackend::Counter(seed) => GameRng::Counter(CounterRng { counter: seed }),
+        }
+    }
+}
+
+// Built generator, kept as an enum so seeded backends can save and rewind their state
+pub enum GameRng {
+    Thread(ThreadRng),
+    Std(Box<ChaCha12Rng>),
+    Small(SmallRng),
+    Counter(CounterRng),
+}
+
+impl GameRng {
+    // Position in the random stream, None for backends whose state can't be read back
+    pub fn state(&self) -> Option<u128> {
+        match self {
+            GameRng::Std(rng) => Some(rng.get_word_pos()),
+            GameRng::Counter(rng) => Some(rng.counter as u128),
+            GameRng::Thread(_) | GameRng::Small(_) => None,
+        }
+    }
+    pub fn set_state(&mut self, state: u128) {
+        match self {
+            GameRng::Std(rng) => rng.set_word_pos(state),
+            GameRng::Counter(rng) => rng.counter = state as u64,
+            GameRng::Thread(_) | GameRng::Small(_) => (),
+        }
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            GameRng::Thread(rng) => rng.next_u32(),
+            GameRng::Std(rng) => rng.next_u32(),
+            GameRng::Small(rng) => rng.next_u32(),
+            GameRng::Counter(rng) => rng.next_u32(),
+        }
+    }
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            GameRng::Thread(rng) => rng.next_u64(),
+            GameRng::Std(rng) => rng.next_u64(),
+            GameRng::Small(rng) => rng.next_u64(),
+            GameRng::Counter(rng) => rng.next_u64(),
+        }
+    }
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        match self {
+            GameRng::Thread(rng) => rng.fill_bytes(dst),
+            GameRng::Std(rng) => rng.fill_bytes(dst),
+            GameRng::Small(rng) => rng.fill_bytes(dst),
+            GameRng::Counter(rng) => rng.fill_bytes(dst),
+        }
+    }
+}
+
+pub struct CounterRng {
+    counter: u64,
+}
+
+impl RngCore for CounterRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+    fn next_u64(&mut self) -> u64 {
+        self.counter = self.counter.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.counter;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        for chunk in dst.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
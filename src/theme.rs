@@ -0,0 +1,115 @@
+use raylib::prelude::*;
+
+// Colors and shapes used when drawing the board
+#[derive(Clone)]
+pub struct Theme {
+    pub background: Color,
+    pub grid: Color,
+    pub head: Color,
+    pub body: Color,
+    // When set, the body fades from `body` behind the head to this color at the tail
+    pub tail: Option<Color>,
+    pub fruit: Color,
+    pub powerup: Color,
+    pub text: Color,
+    // Draws the fruit as an outlined circle so it differs from the snake by shape, not only hue
+    pub round_fruit: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            background: Color::RAYWHITE,
+            grid: Color::LIGHTGRAY,
+            head: Color::DARKBLUE,
+            body: Color::BLUE,
+            tail: None,
+            fruit: Color::GREEN,
+            powerup: Color::PURPLE,
+            text: Color::GRAY,
+            round_fruit: false,
+        }
+    }
+}
+
+impl Theme {
+    // Okabe-Ito hues, distinguishable under the common forms of color blindness
+    pub fn colorblind() -> Self {
+        Self {
+            background: Color::RAYWHITE,
+            grid: Color::LIGHTGRAY,
+            head: Color::new(213, 94, 0, 255),
+            body: Color::new(230, 159, 0, 255),
+            tail: None,
+            fruit: Color::new(0, 114, 178, 255),
+            powerup: Color::new(204, 121, 167, 255),
+            text: Color::DARKGRAY,
+            round_fruit: true,
+        }
+    }
+    // Color of the segment at `idx` in a snake of `len` segments
+    pub fn segment_color(&self, idx: usize, len: usize) -> Color {
+        match self.tail {
+            _ if idx == 0 => self.head,
+            Some(tail) if len > 2 => {
+                lerp_color(self.body, tail, (idx - 1) as f32 / (len - 2) as f32)
+            }
+            _ => self.body,
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Theme::default()),
+            "colorblind" => Some(Theme::colorblind()),
+            _ => None,
+        }
+    }
+}
+
+// Straight blend between two colors, `t` running from 0 at `from` to 1 at `to`
+pub fn lerp_color(from: Color, to: Color, t: f32) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    Color::new(
+        mix(from.r, to.r),
+        mix(from.g, to.g),
+        mix(from.b, to.b),
+        mix(from.a, to.a),
+    )
+}
+
+// Opaque color from a hex string like "ff8800", with or without a leading '#'
+pub fn parse_hex(text: &str) -> Option<Color> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some(Color::new(channel(0)?, channel(2)?, channel(4)?, 255))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colorblind_preset_differs_from_default() {
+        let default = Theme::default();
+        let colorblind = Theme::from_name("colorblind").unwrap();
+        assert_ne!(colorblind.head, default.head);
+        assert_ne!(colorblind.body, default.body);
+        assert_ne!(colorblind.fruit, default.fruit);
+        assert!(colorblind.round_fruit && !default.round_fruit);
+        assert!(Theme::from_name("sepia").is_none());
+    }
+
+    #[test]
+    fn lerp_hits_both_ends_and_the_middle() {
+        let from = Color::new(0, 0, 0, 255);
+        let to = Color::new(200, 100, 50, 255);
+        assert_eq!(lerp_color(from, to, 0.0), from);
+        assert_eq!(lerp_color(from, to, 1.0), to);
+        assert_eq!(lerp_color(from, to, 0.5), Color::new(100, 50, 25, 255));
+        assert_eq!(lerp_color(from, to, 2.0), to);
+    }
+}